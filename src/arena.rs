@@ -0,0 +1,305 @@
+//! The playing field: its bounds, the map laid out in it, the walls that block it, where players
+//! spawn in it, and the camera looking at it.
+
+use std::fmt;
+
+use bevy::{ecs::system::SystemParam, prelude::*};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    combat::{Bullet, Velocity},
+    input::apply_tick_inputs,
+    player::{player_movement, Player, ID},
+    GameSet,
+};
+
+/// Random candidates scored when the map has no [`SpawnPoint`]s.
+const SPAWN_CANDIDATES: usize = 16;
+/// How far ahead, in seconds, a bullet's path counts as a threat to a spawn point.
+const SPAWN_BULLET_HORIZON: f32 = 1.0;
+/// Score lost per bullet heading for a spawn point, in the same units as enemy distance.
+const SPAWN_BULLET_PENALTY: f32 = 200.0;
+/// How far in from the edges of the arena the outposts are.
+const OUTPOST_INSET: f32 = 100.0;
+/// How thick walls are.
+const WALL_THICKNESS: f32 = 40.0;
+const WALL_COLOR: Color = Color::rgb(0.35, 0.35, 0.4);
+/// Walls are drawn under players but over bullets, which never get inside them anyway.
+const WALL_Z: f32 = -1.0;
+
+pub struct ArenaPlugin;
+
+impl Plugin for ArenaPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<Arena>()
+            .init_resource::<Arena>()
+            .init_resource::<Map>()
+            .register_type::<SpawnPoint>()
+            .register_type::<Wall>()
+            .add_systems(Startup, setup_camera)
+            .add_systems(
+                FixedUpdate,
+                // Players joining this tick can already spawn at the map's spawn points.
+                (lay_out_map, apply_deferred)
+                    .chain()
+                    .in_set(GameSet::ApplyInputs)
+                    .after(apply_tick_inputs),
+            )
+            .add_systems(
+                FixedUpdate,
+                keep_players_out_of_walls
+                    .in_set(GameSet::Players)
+                    .after(player_movement),
+            );
+    }
+}
+
+/// Half the width and height of the playing field, centered on the origin.
+#[derive(Resource, Default, Reflect)]
+#[reflect(Resource)]
+pub struct Arena {
+    pub half_size: Vec2,
+}
+
+impl Arena {
+    pub fn random_point(&self, rng: &mut impl Rng) -> Vec2 {
+        Vec2::new(
+            rng.gen_range(-self.half_size.x..=self.half_size.x),
+            rng.gen_range(-self.half_size.y..=self.half_size.y),
+        )
+    }
+
+    pub fn contains(&self, point: Vec2) -> bool {
+        point.abs().cmple(self.half_size).all()
+    }
+}
+
+/// What is laid out in the arena, which fits itself to the arena's size. Everyone playing a
+/// match, and every replay of it, has to use the same one.
+#[derive(Resource, Clone, Copy, PartialEq, Eq, Debug, Default, Serialize, Deserialize)]
+pub enum Map {
+    /// Nothing but open ground, with players spawning anywhere.
+    #[default]
+    Open,
+    /// Players spawn at the corners of the arena and the middles of its long sides, with walls
+    /// to take cover behind between them and the middle.
+    Outposts,
+}
+
+impl Map {
+    pub const ALL: [Map; 2] = [Map::Open, Map::Outposts];
+
+    /// Where players spawn in an arena of the given size, or nowhere in particular if empty.
+    pub fn spawn_points(self, half_size: Vec2) -> Vec<Vec2> {
+        match self {
+            Map::Open => vec![],
+            Map::Outposts => {
+                let inset = (half_size - Vec2::splat(OUTPOST_INSET)).max(Vec2::ZERO);
+                [-1.0, 0.0, 1.0]
+                    .into_iter()
+                    .flat_map(|x| [-1.0, 1.0].map(|y| Vec2::new(x, y) * inset))
+                    .collect()
+            }
+        }
+    }
+
+    /// The walls standing in an arena of the given size.
+    pub fn walls(self, half_size: Vec2) -> Vec<Rect> {
+        match self {
+            Map::Open => vec![],
+            Map::Outposts => {
+                let size = Vec2::new(WALL_THICKNESS, half_size.y * 0.4);
+                [-1.0, 1.0]
+                    .into_iter()
+                    .flat_map(|x| [-1.0, 1.0].map(|y| Vec2::new(x, y) * half_size * 0.4))
+                    .map(|center| Rect::from_center_size(center, size))
+                    .collect()
+            }
+        }
+    }
+
+    /// Everything laid out in an arena of the given size.
+    pub fn features(self, half_size: Vec2) -> Vec<MapFeature> {
+        let spawn_points = self.spawn_points(half_size).into_iter();
+        let walls = self.walls(half_size).into_iter();
+        spawn_points
+            .map(MapFeature::SpawnPoint)
+            .chain(walls.map(MapFeature::Wall))
+            .collect()
+    }
+}
+
+impl fmt::Display for Map {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Map::Open => "open",
+            Map::Outposts => "outposts",
+        })
+    }
+}
+
+/// A map-authored place to spawn players. When any exist, players only spawn at these.
+#[derive(Component, Default, Reflect)]
+#[reflect(Component)]
+pub struct SpawnPoint;
+
+/// Something the [`Map`] put in the arena, laid out again whenever the arena changes size.
+#[derive(Component, Clone, Copy, PartialEq, Debug)]
+pub enum MapFeature {
+    SpawnPoint(Vec2),
+    Wall(Rect),
+}
+
+/// Blocks players and bullets. Its transform's scale is its size.
+#[derive(Component, Default, Reflect)]
+#[reflect(Component)]
+pub struct Wall;
+
+/// Lays the map out again when it or the arena changes, leaving it alone if everything is
+/// already in place.
+pub fn lay_out_map(
+    mut commands: Commands,
+    map: Res<Map>,
+    arena: Res<Arena>,
+    laid_out: Query<(Entity, &MapFeature)>,
+) {
+    if !map.is_changed() && !arena.is_changed() {
+        return;
+    }
+    let features = map.features(arena.half_size);
+    // Different kinds of features are stored apart, so they come back in no particular order.
+    if laid_out.iter().len() == features.len()
+        && laid_out
+            .iter()
+            .all(|(_, feature)| features.contains(feature))
+    {
+        return;
+    }
+    for (entity, _) in &laid_out {
+        commands.entity(entity).despawn();
+    }
+    for feature in features {
+        match feature {
+            MapFeature::SpawnPoint(point) => commands.spawn((
+                TransformBundle::from_transform(Transform::from_translation(point.extend(0.0))),
+                SpawnPoint,
+                feature,
+                Name::new("Spawn point"),
+            )),
+            MapFeature::Wall(rect) => commands.spawn((
+                SpriteBundle {
+                    sprite: Sprite {
+                        color: WALL_COLOR,
+                        custom_size: Some(Vec2::ONE),
+                        ..default()
+                    },
+                    transform: Transform::from_translation(rect.center().extend(WALL_Z))
+                        .with_scale(rect.size().extend(1.0)),
+                    ..default()
+                },
+                Wall,
+                feature,
+                Name::new("Wall"),
+            )),
+        };
+    }
+}
+
+/// Pushes players back out of any wall they walked into, the shortest way.
+pub fn keep_players_out_of_walls(
+    mut players: Query<&mut Transform, (With<Player>, Without<Wall>)>,
+    walls: Query<&Transform, With<Wall>>,
+) {
+    for mut transform in &mut players {
+        for wall in &walls {
+            let reach = (wall.scale.truncate() + transform.scale.truncate()) / 2.0;
+            let offset = (transform.translation - wall.translation).truncate();
+            let overlap = reach - offset.abs();
+            if overlap.x <= 0.0 || overlap.y <= 0.0 {
+                continue;
+            }
+            if overlap.x < overlap.y {
+                transform.translation.x += overlap.x * offset.x.signum();
+            } else {
+                transform.translation.y += overlap.y * offset.y.signum();
+            }
+        }
+    }
+}
+
+/// Picks where a player (re)spawns: as far as possible from living enemies and out of the way
+/// of bullets in flight.
+#[derive(SystemParam)]
+pub struct SpawnSelector<'w, 's> {
+    arena: Res<'w, Arena>,
+    spawn_points: Query<'w, 's, &'static Transform, (With<SpawnPoint>, Without<Player>)>,
+    bullets: Query<
+        'w,
+        's,
+        (&'static Transform, &'static Velocity, &'static ID),
+        (With<Bullet>, Without<Player>),
+    >,
+}
+
+impl SpawnSelector<'_, '_> {
+    /// Candidates at least `min_enemy_distance` away from every enemy are preferred; among
+    /// those, or among all if none qualify, the best scoring one wins.
+    pub fn choose(
+        &self,
+        rng: &mut impl Rng,
+        id: usize,
+        enemies: &[Vec2],
+        min_enemy_distance: f32,
+        player_size: f32,
+    ) -> Vec2 {
+        let mut candidates: Vec<Vec2> = self
+            .spawn_points
+            .iter()
+            .map(|transform| transform.translation.truncate())
+            .collect();
+        if candidates.is_empty() {
+            candidates = (0..SPAWN_CANDIDATES)
+                .map(|_| self.arena.random_point(rng))
+                .collect();
+        }
+        // Never let distance outweigh bullets when there are no enemies at all.
+        let max_distance = self.arena.half_size.length() * 2.0;
+        let rate = |candidate: Vec2| {
+            let enemy_distance = enemies
+                .iter()
+                .map(|enemy| enemy.distance(candidate))
+                .fold(max_distance, f32::min);
+            let threats = self
+                .bullets
+                .iter()
+                .filter(|(_, _, owner)| owner.0 != id)
+                .filter(|(transform, velocity, _)| {
+                    let start = transform.translation.truncate();
+                    let speed_squared = velocity.length_squared();
+                    let t = if speed_squared > 0.0 {
+                        ((candidate - start).dot(velocity.0) / speed_squared)
+                            .clamp(0.0, SPAWN_BULLET_HORIZON)
+                    } else {
+                        0.0
+                    };
+                    (start + velocity.0 * t).distance(candidate) < player_size
+                })
+                .count();
+            let score = enemy_distance - threats as f32 * SPAWN_BULLET_PENALTY;
+            (enemy_distance >= min_enemy_distance, score)
+        };
+        candidates
+            .into_iter()
+            .map(|candidate| (rate(candidate), candidate))
+            .max_by(|((a_safe, a_score), _), ((b_safe, b_score), _)| {
+                a_safe.cmp(b_safe).then(a_score.total_cmp(b_score))
+            })
+            .map_or(Vec2::ZERO, |(_, candidate)| candidate)
+    }
+}
+
+pub fn setup_camera(mut commands: Commands) {
+    let camera = Camera2dBundle::default();
+    commands.spawn(camera);
+}
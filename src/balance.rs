@@ -0,0 +1,198 @@
+//! Plays many headless bot matches for each of several settings, spread over all the CPU's
+//! cores, and sums up how each bot did, so settings can be balanced with numbers instead of
+//! hunches.
+
+use std::{
+    io::{self, Write},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+    thread,
+};
+
+use bevy::prelude::*;
+
+use crate::{
+    arena::Map,
+    bot::{Bots, Difficulty, BOT_ID_OFFSET},
+    headless::{HeadlessInputs, HeadlessPlugin},
+    settings::Settings,
+    stats::{csv_field, MatchStats, Mode},
+    GamePlugins,
+};
+
+/// The columns of [`write_csv`]'s output.
+pub const CSV_HEADER: &str = "variant,bot,difficulty,personality,matches,wins,draws,win_rate,\
+                              kills_per_match,deaths_per_match,shots_per_match,accuracy,\
+                              damage_dealt_per_match,damage_taken_per_match,mean_time_to_kill";
+
+pub struct Balance {
+    /// The named settings to compare.
+    pub variants: Vec<(String, Settings)>,
+    /// The bots, the same in every match. Each match reseeds them from its own seed.
+    pub bots: Bots,
+    pub map: Map,
+    pub mode: Mode,
+    /// How many matches to play with each variant.
+    pub matches: usize,
+    pub ticks: u32,
+    /// Each match is seeded with this plus its number, so a run can be repeated exactly.
+    pub seed: u64,
+}
+
+/// How one bot did over all the matches played with one variant.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct BalanceRow {
+    pub variant: String,
+    pub bot: usize,
+    pub difficulty: Difficulty,
+    pub personality: String,
+    pub matches: u32,
+    pub wins: u32,
+    pub draws: u32,
+    pub kills: u32,
+    pub deaths: u32,
+    pub shots: u32,
+    pub hits: u32,
+    pub damage_taken: u32,
+    pub times_to_kill: Vec<f32>,
+}
+
+impl Balance {
+    /// Plays a single match with the given variant and seed.
+    pub fn play_match(&self, variant: usize, seed: u64) -> MatchStats {
+        let settings = &self.variants[variant].1;
+        let mut bots = self.bots.clone();
+        bots.reseed(seed.wrapping_add(1));
+        let mut app = App::new();
+        app.add_plugins(GamePlugins)
+            .insert_resource(settings.player_config.clone())
+            .insert_resource(settings.bullet_config.clone())
+            .insert_resource(self.map)
+            .insert_resource(self.mode)
+            .add_plugins(HeadlessPlugin {
+                inputs: HeadlessInputs::Bots(Box::new(bots)),
+                ticks: None,
+                seed: Some(seed),
+            });
+        // The first update only runs startup.
+        for _ in 0..=self.ticks {
+            app.update();
+        }
+        app.world
+            .remove_resource::<MatchStats>()
+            .expect("the stats plugin keeps match stats")
+    }
+
+    /// Plays every match on `threads` threads, calling `progress` with how many are done out of
+    /// how many after each, and returns a row for every bot with every variant.
+    pub fn run(&self, threads: usize, progress: impl Fn(usize, usize) + Sync) -> Vec<BalanceRow> {
+        let mut rows: Vec<BalanceRow> = self
+            .variants
+            .iter()
+            .flat_map(|(variant, _)| {
+                self.bots
+                    .bots
+                    .iter()
+                    .enumerate()
+                    .map(|(number, bot)| BalanceRow {
+                        variant: variant.clone(),
+                        bot: number,
+                        difficulty: bot.difficulty,
+                        personality: bot.personality_name.clone(),
+                        ..default()
+                    })
+            })
+            .collect();
+
+        let total = self.variants.len() * self.matches;
+        let next = AtomicUsize::new(0);
+        let finished = Mutex::new((0, vec![None; total]));
+        thread::scope(|scope| {
+            for _ in 0..threads.max(1) {
+                scope.spawn(|| loop {
+                    let number = next.fetch_add(1, Ordering::Relaxed);
+                    if number >= total {
+                        break;
+                    }
+                    let variant = number / self.matches;
+                    let stats = self.play_match(variant, self.seed.wrapping_add(number as u64));
+                    let mut finished = finished.lock().unwrap();
+                    let (count, matches) = &mut *finished;
+                    matches[number] = Some(stats);
+                    *count += 1;
+                    progress(*count, total);
+                });
+            }
+        });
+
+        // Summed up in order, so the rows don't depend on which thread finished first.
+        let (_, matches) = finished.into_inner().unwrap();
+        for (number, stats) in matches.into_iter().enumerate() {
+            let bots = self.bots.bots.len();
+            let first_row = number / self.matches * bots;
+            let stats = stats.expect("every match is played");
+            self.add_match(&mut rows[first_row..first_row + bots], &stats);
+        }
+        rows
+    }
+
+    /// Adds a match's stats to the rows of the bots that played it.
+    fn add_match(&self, rows: &mut [BalanceRow], stats: &MatchStats) {
+        let winner = stats.winner().map(|winner| winner.id);
+        for row in rows {
+            let id = BOT_ID_OFFSET + row.bot;
+            row.matches += 1;
+            match winner {
+                Some(winner) if winner == id => row.wins += 1,
+                Some(_) => {}
+                None => row.draws += 1,
+            }
+            let Some(player) = stats.players.iter().find(|player| player.id == id) else {
+                continue;
+            };
+            row.kills += player.kills;
+            row.deaths += player.deaths;
+            row.shots += player.shots;
+            row.hits += player.hits;
+            row.damage_taken += player.damage_taken;
+            row.times_to_kill.extend(&player.times_to_kill);
+        }
+    }
+}
+
+/// Writes the rows as CSV, with a header. Bullets do one damage, so damage dealt is hits.
+pub fn write_csv(rows: &[BalanceRow], mut out: impl Write) -> io::Result<()> {
+    writeln!(out, "{CSV_HEADER}")?;
+    for row in rows {
+        let per_match = |total: u32| total as f32 / row.matches.max(1) as f32;
+        let mean_time_to_kill = match row.times_to_kill.len() {
+            0 => String::new(),
+            kills => format!(
+                "{:.3}",
+                row.times_to_kill.iter().sum::<f32>() / kills as f32
+            ),
+        };
+        writeln!(
+            out,
+            "{},{},{},{},{},{},{},{:.3},{:.3},{:.3},{:.1},{:.3},{:.3},{:.3},{}",
+            csv_field(&row.variant),
+            row.bot,
+            row.difficulty,
+            csv_field(&row.personality),
+            row.matches,
+            row.wins,
+            row.draws,
+            per_match(row.wins),
+            per_match(row.kills),
+            per_match(row.deaths),
+            per_match(row.shots),
+            row.hits as f32 / row.shots.max(1) as f32,
+            per_match(row.hits),
+            per_match(row.damage_taken),
+            mean_time_to_kill,
+        )?;
+    }
+    Ok(())
+}
@@ -0,0 +1,212 @@
+//! What bots decide to do, scored by utility. A [`Personality`] is a list of behaviors, each an
+//! [`Action`] with a weight and considerations that scale it by how well it suits the
+//! [`Situation`]. The highest scoring action wins.
+//!
+//! Personalities are RON files. The built-in ones are in `assets/bots`, and more can be put in
+//! the `bots` directory of the user's config directory, replacing built-in ones of the same
+//! name.
+
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::settings::config_dir;
+
+pub const DEFAULT_PERSONALITY: &str = "balanced";
+const PERSONALITY_DIRECTORY: &str = "bots";
+const BUILTIN_PERSONALITIES: [(&str, &str); 3] = [
+    ("balanced", include_str!("../assets/bots/balanced.ron")),
+    ("aggressive", include_str!("../assets/bots/aggressive.ron")),
+    ("cautious", include_str!("../assets/bots/cautious.ron")),
+];
+
+/// Something a bot can do with its movement. Aiming is always at its target.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum Action {
+    /// Head for the target until within the preferred distance.
+    Chase,
+    /// Circle the target at the preferred distance.
+    Strafe,
+    /// Run from the target.
+    Retreat,
+    /// Step out of the way of the bullet about to hit.
+    Dodge,
+    /// Get behind the wall closest to hide from the target behind.
+    TakeCover,
+    /// Roam in a random direction.
+    Wander,
+}
+
+impl Action {
+    /// Whether the action means anything in the situation, which for some takes a target or
+    /// a wall to hide behind.
+    pub fn is_possible(self, situation: &Situation) -> bool {
+        match self {
+            Action::Chase | Action::Strafe | Action::Retreat => situation.target_distance.is_some(),
+            Action::TakeCover => situation.cover_distance.is_some(),
+            Action::Dodge | Action::Wander => true,
+        }
+    }
+}
+
+/// Something about the situation a consideration looks at.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum Input {
+    /// The bot's own health, from 0 to 1.
+    Health,
+    /// The target's health, from 0 to 1.
+    TargetHealth,
+    /// How far away the target is.
+    TargetDistance,
+    /// How soon the next bullet hits, from 0 when none is seen coming to 1 when it is about to.
+    Danger,
+    /// How far away the closest cover from the target is.
+    CoverDistance,
+}
+
+/// Scores an input 0 at `from` and 1 at `to`, along a straight line between them and clamped
+/// outside. `from` can be above `to`, for scores that fall as the input grows.
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct Consideration {
+    pub input: Input,
+    pub from: f32,
+    pub to: f32,
+}
+
+impl Consideration {
+    pub fn score(&self, situation: &Situation) -> f32 {
+        let Some(value) = situation.value(self.input) else {
+            return 0.0;
+        };
+        if self.from == self.to {
+            return if value >= self.to { 1.0 } else { 0.0 };
+        }
+        ((value - self.from) / (self.to - self.from)).clamp(0.0, 1.0)
+    }
+}
+
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct Behavior {
+    pub action: Action,
+    pub weight: f32,
+    /// Multiplied together with the weight. None means the weight alone.
+    #[serde(default)]
+    pub considerations: Vec<Consideration>,
+}
+
+impl Behavior {
+    pub fn score(&self, situation: &Situation) -> f32 {
+        if !self.action.is_possible(situation) {
+            return 0.0;
+        }
+        self.considerations
+            .iter()
+            .map(|consideration| consideration.score(situation))
+            .product::<f32>()
+            * self.weight
+    }
+}
+
+/// What a bot knows of the match when it decides what to do.
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct Situation {
+    pub health: f32,
+    pub target_health: Option<f32>,
+    pub target_distance: Option<f32>,
+    pub danger: f32,
+    /// None when there is no target or no wall to hide from it behind.
+    pub cover_distance: Option<f32>,
+}
+
+impl Situation {
+    fn value(&self, input: Input) -> Option<f32> {
+        match input {
+            Input::Health => Some(self.health),
+            Input::TargetHealth => self.target_health,
+            Input::TargetDistance => self.target_distance,
+            Input::Danger => Some(self.danger),
+            Input::CoverDistance => self.cover_distance,
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct Personality {
+    /// How far from their target bots like to fight.
+    pub preferred_distance: f32,
+    pub behaviors: Vec<Behavior>,
+}
+
+impl Personality {
+    /// The highest scoring action, the first listed of those tied, or `None` if nothing scores
+    /// above 0.
+    pub fn choose(&self, situation: &Situation) -> Option<Action> {
+        let mut best = None;
+        let mut best_score = 0.0;
+        for behavior in &self.behaviors {
+            let score = behavior.score(situation);
+            if score > best_score {
+                best = Some(behavior.action);
+                best_score = score;
+            }
+        }
+        best
+    }
+
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let text = fs::read_to_string(path)?;
+        ron::from_str(&text).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+    }
+
+    /// The personalities that come with the game, by name.
+    pub fn builtin() -> Vec<(String, Personality)> {
+        BUILTIN_PERSONALITIES
+            .iter()
+            .map(|(name, text)| {
+                let personality = ron::from_str(text)
+                    .unwrap_or_else(|err| panic!("built-in personality {name}: {err}"));
+                (name.to_string(), personality)
+            })
+            .collect()
+    }
+
+    /// The built-in personalities, with the user's added to or replacing them. This runs before
+    /// the app, and so logging, is set up, so a file that can't be used is reported on stderr.
+    pub fn load_all() -> Vec<(String, Personality)> {
+        let mut personalities = Personality::builtin();
+        let entries = config_dir()
+            .map(|directory| directory.join(PERSONALITY_DIRECTORY))
+            .and_then(|directory| fs::read_dir(directory).ok());
+        let mut paths: Vec<PathBuf> = entries
+            .into_iter()
+            .flatten()
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.extension().is_some_and(|extension| extension == "ron"))
+            .collect();
+        paths.sort();
+        for path in paths {
+            let Some(name) = path
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().into_owned())
+            else {
+                continue;
+            };
+            match Personality::load(&path) {
+                Ok(personality) => {
+                    match personalities
+                        .iter_mut()
+                        .find(|(existing, _)| *existing == name)
+                    {
+                        Some((_, existing)) => *existing = personality,
+                        None => personalities.push((name, personality)),
+                    }
+                }
+                Err(err) => eprintln!("warning: ignoring personality {}: {err}", path.display()),
+            }
+        }
+        personalities
+    }
+}
@@ -0,0 +1,514 @@
+//! Computer controlled players. Bots join, play and leave through [`TickInputs`] like
+//! controllers do, so the simulation treats them like anyone else and replays record what they
+//! did without running them again.
+//!
+//! Each bot picks the closest enemy and leads its shots by where the target is heading. How it
+//! moves, including hiding behind walls, is up to its [`Personality`], and how well it does all
+//! that depends on its [`Difficulty`]. Turning is left to the usual rotation, so bots turn no
+//! faster than `turning_speed` allows.
+
+use std::fmt;
+
+use bevy::prelude::*;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    arena::{Arena, Wall},
+    behavior::{Action, Personality, Situation},
+    combat::{Bullet, BulletConfig, Velocity},
+    input::{read_live_inputs, Connection, PlayerInput, TickInputs},
+    player::{Alive, Health, Player, PlayerConfig, PlayerOverrides, ID},
+    replay::is_playing_back,
+    GameSet,
+};
+
+/// Controllers count their IDs up from 0, so bots take theirs from here on. Low enough that
+/// bots, which sit at z = ID like every player, stay under the HUD.
+pub const BOT_ID_OFFSET: usize = 64;
+/// How far from the preferred distance is close enough.
+const DISTANCE_TOLERANCE: f32 = 100.0;
+/// How close to the edge of the arena bots get before turning back.
+const EDGE_MARGIN: f32 = 80.0;
+/// How much further than the closest enemy a bot's current target can be before it switches.
+const TARGET_STICKINESS: f32 = 1.3;
+/// How close to its cover spot a bot has to be to stop there.
+const COVER_TOLERANCE: f32 = 20.0;
+/// The chance of changing strafing or wandering direction at each decision.
+const STRAFE_FLIP_CHANCE: f64 = 0.2;
+
+pub fn is_bot(id: usize) -> bool {
+    id >= BOT_ID_OFFSET
+}
+
+pub struct BotPlugin;
+
+impl Plugin for BotPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Bots>().add_systems(
+            FixedUpdate,
+            drive_bots
+                .in_set(GameSet::ReadInputs)
+                .after(read_live_inputs)
+                .run_if(not(is_playing_back)),
+        );
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, Serialize, Deserialize)]
+pub enum Difficulty {
+    Easy,
+    #[default]
+    Normal,
+    Hard,
+}
+
+impl Difficulty {
+    pub const ALL: [Difficulty; 3] = [Difficulty::Easy, Difficulty::Normal, Difficulty::Hard];
+
+    pub fn skill(self) -> BotSkill {
+        match self {
+            Difficulty::Easy => BotSkill {
+                reaction_time: 0.5,
+                aim_error: 0.3,
+                dodge_horizon: 0.0,
+            },
+            Difficulty::Normal => BotSkill {
+                reaction_time: 0.25,
+                aim_error: 0.12,
+                dodge_horizon: 0.5,
+            },
+            Difficulty::Hard => BotSkill {
+                reaction_time: 0.1,
+                aim_error: 0.03,
+                dodge_horizon: 1.0,
+            },
+        }
+    }
+}
+
+impl fmt::Display for Difficulty {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Difficulty::Easy => "easy",
+            Difficulty::Normal => "normal",
+            Difficulty::Hard => "hard",
+        })
+    }
+}
+
+/// How a bot of some [`Difficulty`] plays.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct BotSkill {
+    /// Seconds between looking at the match and deciding what to do about it.
+    pub reaction_time: f32,
+    /// The most, in radians, a bot's aim is off by.
+    pub aim_error: f32,
+    /// How many seconds ahead a bot sees bullets coming at it, 0 for not dodging at all.
+    pub dodge_horizon: f32,
+}
+
+/// What a bot last saw of its target: where it was, how fast it was going and how healthy it
+/// was.
+#[derive(Clone, Copy)]
+struct Sighting {
+    id: usize,
+    position: Vec2,
+    velocity: Vec2,
+    health: f32,
+    age: f32,
+}
+
+#[derive(Clone)]
+pub struct Bot {
+    pub id: usize,
+    pub difficulty: Difficulty,
+    pub personality_name: String,
+    pub personality: Personality,
+    /// What the bot decided to do last.
+    pub action: Option<Action>,
+    leaving: bool,
+    connected: bool,
+    reaction: Timer,
+    target: Option<Sighting>,
+    aim_error: f32,
+    movement: Vec2,
+    strafe: f32,
+    wander: Vec2,
+    /// The closest spot with a wall between the bot's target and it.
+    cover: Option<Vec2>,
+    /// Every player's position on the previous tick, to tell how fast they are going.
+    last_positions: Vec<(usize, Vec2)>,
+}
+
+/// The bots in the match, and the personalities they can have.
+#[derive(Resource, Clone)]
+pub struct Bots {
+    pub bots: Vec<Bot>,
+    pub personalities: Vec<(String, Personality)>,
+    rng: StdRng,
+}
+
+impl Default for Bots {
+    fn default() -> Self {
+        Bots::new(None, Personality::builtin())
+    }
+}
+
+impl Bots {
+    /// With a seed, bots make the same decisions every time, given the same match.
+    pub fn new(seed: Option<u64>, personalities: Vec<(String, Personality)>) -> Self {
+        Bots {
+            bots: vec![],
+            personalities,
+            rng: match seed {
+                Some(seed) => StdRng::seed_from_u64(seed),
+                None => StdRng::from_entropy(),
+            },
+        }
+    }
+
+    pub fn seeded(seed: u64) -> Self {
+        Bots::new(Some(seed), Personality::builtin())
+    }
+
+    /// Starts the bots' decisions over from a seed, to play another match the same way.
+    pub fn reseed(&mut self, seed: u64) {
+        self.rng = StdRng::seed_from_u64(seed);
+    }
+
+    /// Adds a bot with the named personality, which joins on the next tick. Returns its ID.
+    pub fn add(&mut self, difficulty: Difficulty, personality: &str) -> Result<usize, String> {
+        let Some((personality_name, personality)) = self
+            .personalities
+            .iter()
+            .find(|(name, _)| name == personality)
+            .cloned()
+        else {
+            return Err(format!("there is no bot personality called {personality}"));
+        };
+        let id = (BOT_ID_OFFSET..)
+            .find(|id| self.bots.iter().all(|bot| bot.id != *id))
+            .unwrap();
+        let skill = difficulty.skill();
+        self.bots.push(Bot {
+            id,
+            difficulty,
+            personality_name,
+            personality,
+            action: None,
+            leaving: false,
+            connected: false,
+            reaction: Timer::from_seconds(skill.reaction_time, TimerMode::Repeating),
+            target: None,
+            aim_error: 0.0,
+            movement: Vec2::ZERO,
+            strafe: 1.0,
+            wander: Vec2::X,
+            cover: None,
+            last_positions: vec![],
+        });
+        Ok(id)
+    }
+
+    /// Makes a bot leave the match the way players do, by holding Select.
+    pub fn remove(&mut self, id: usize) {
+        if let Some(bot) = self.bots.iter_mut().find(|bot| bot.id == id) {
+            bot.leaving = true;
+        }
+    }
+
+    /// The bots still playing, or about to.
+    pub fn playing(&self) -> impl Iterator<Item = &Bot> {
+        self.bots.iter().filter(|bot| !bot.leaving)
+    }
+}
+
+/// Where to aim to hit something at `target` moving at `velocity` with a bullet from `from`,
+/// or straight at it if the bullet can't catch up.
+pub fn lead_target(from: Vec2, target: Vec2, velocity: Vec2, bullet_speed: f32) -> Vec2 {
+    // Solve |target + velocity * t - from| = bullet_speed * t for the soonest t > 0.
+    let offset = target - from;
+    let a = velocity.length_squared() - bullet_speed * bullet_speed;
+    let b = 2.0 * offset.dot(velocity);
+    let c = offset.length_squared();
+    let t = if a.abs() < f32::EPSILON {
+        (b != 0.0).then(|| -c / b)
+    } else {
+        let discriminant = b * b - 4.0 * a * c;
+        (discriminant >= 0.0).then(|| {
+            let root = discriminant.sqrt();
+            let (t1, t2) = ((-b - root) / (2.0 * a), (-b + root) / (2.0 * a));
+            match (t1 > 0.0, t2 > 0.0) {
+                (true, true) => t1.min(t2),
+                (true, false) => t1,
+                _ => t2,
+            }
+        })
+    };
+    match t.filter(|t| *t > 0.0) {
+        Some(t) => target + velocity * t,
+        None => target,
+    }
+}
+
+pub fn drive_bots(
+    mut bots: ResMut<Bots>,
+    mut tick_inputs: ResMut<TickInputs>,
+    players: Query<
+        (
+            &ID,
+            &Transform,
+            &Health,
+            Option<&PlayerOverrides>,
+            Option<&Alive>,
+        ),
+        With<Player>,
+    >,
+    bullets: Query<(&Transform, &Velocity, &ID), With<Bullet>>,
+    walls: Query<&Transform, With<Wall>>,
+    player_config: Res<PlayerConfig>,
+    bullet_config: Res<BulletConfig>,
+    arena: Res<Arena>,
+    fixed_time: Res<FixedTime>,
+) {
+    let period = fixed_time.period.as_secs_f32();
+    let bots = &mut *bots;
+    let rng = &mut bots.rng;
+    let health = |health: &Health, overrides| {
+        health.current_health as f32
+            / player_config.with_overrides(overrides).starting_health as f32
+    };
+    let living: Vec<(usize, Vec2, f32)> = players
+        .iter()
+        .filter(|(.., alive)| alive.is_some())
+        .map(|(id, transform, player_health, overrides, _)| {
+            (
+                id.0,
+                transform.translation.truncate(),
+                health(player_health, overrides),
+            )
+        })
+        .collect();
+    bots.bots.retain(|bot| {
+        let joined = players.iter().any(|(id, ..)| id.0 == bot.id);
+        if bot.leaving && !joined && bot.connected {
+            tick_inputs.disconnected.push(bot.id);
+        }
+        !bot.leaving || joined
+    });
+
+    for bot in &mut bots.bots {
+        let mut input = PlayerInput {
+            id: bot.id,
+            ..default()
+        };
+        let player = players.iter().find(|(id, ..)| id.0 == bot.id);
+        let Some((_, transform, own_health, overrides, alive)) = player else {
+            if !bot.connected {
+                tick_inputs.connected.push(Connection {
+                    id: bot.id,
+                    name: format!("{} bot ({})", bot.personality_name, bot.difficulty),
+                });
+                bot.connected = true;
+            }
+            input.pressed.push(GamepadButtonType::Start);
+            tick_inputs.players.push(input);
+            continue;
+        };
+        if bot.leaving {
+            input.held.push(GamepadButtonType::Select);
+            tick_inputs.players.push(input);
+            continue;
+        }
+        bot.reaction.tick(fixed_time.period);
+        if alive.is_none() {
+            bot.target = None;
+            if bot.reaction.just_finished() {
+                input.pressed.push(GamepadButtonType::Mode);
+            }
+            tick_inputs.players.push(input);
+            continue;
+        }
+
+        let position = transform.translation.truncate();
+        let sightings: Vec<Sighting> = living
+            .iter()
+            .map(|&(id, current, health)| {
+                let last = bot
+                    .last_positions
+                    .iter()
+                    .find(|(last_id, _)| *last_id == id)
+                    .map_or(current, |(_, last)| *last);
+                Sighting {
+                    id,
+                    position: current,
+                    velocity: (current - last) / period,
+                    health,
+                    age: 0.0,
+                }
+            })
+            .collect();
+        bot.last_positions = living
+            .iter()
+            .map(|&(id, position, _)| (id, position))
+            .collect();
+
+        if let Some(target) = &mut bot.target {
+            target.age += period;
+        }
+        if bot.reaction.just_finished() || bot.target.is_none() {
+            let skill = bot.difficulty.skill();
+            let enemies = || sightings.iter().filter(|sighting| sighting.id != bot.id);
+            let distance = |sighting: &Sighting| sighting.position.distance(position);
+            let closest = enemies().min_by(|a, b| distance(a).total_cmp(&distance(b)));
+            let current = bot
+                .target
+                .and_then(|target| enemies().find(|sighting| sighting.id == target.id));
+            bot.target = match (current, closest) {
+                (Some(current), Some(closest))
+                    if distance(current) <= distance(closest) * TARGET_STICKINESS =>
+                {
+                    Some(*current)
+                }
+                (_, closest) => closest.copied(),
+            };
+            bot.aim_error = match skill.aim_error > 0.0 {
+                true => rng.gen_range(-skill.aim_error..=skill.aim_error),
+                false => 0.0,
+            };
+            if rng.gen_bool(STRAFE_FLIP_CHANCE) {
+                bot.strafe = -bot.strafe;
+            }
+            if rng.gen_bool(STRAFE_FLIP_CHANCE) {
+                bot.wander = Vec2::from_angle(rng.gen_range(0.0..std::f32::consts::TAU));
+            }
+            let threat = incoming_bullet(
+                bot.id,
+                position,
+                skill,
+                &bullets,
+                &player_config,
+                &bullet_config,
+            );
+            let by_distance =
+                |a: &Vec2, b: &Vec2| a.distance(position).total_cmp(&b.distance(position));
+            bot.cover = bot.target.and_then(|target| {
+                walls
+                    .iter()
+                    .map(|wall| cover_behind(wall, target.position, player_config.scale))
+                    .min_by(by_distance)
+            });
+            let situation = Situation {
+                health: health(own_health, overrides),
+                target_health: bot.target.map(|target| target.health),
+                target_distance: bot.target.map(|target| target.position.distance(position)),
+                danger: threat.map_or(0.0, |(time, ..)| 1.0 - time / skill.dodge_horizon),
+                cover_distance: bot.cover.map(|cover| cover.distance(position)),
+            };
+            bot.action = bot.personality.choose(&situation);
+            bot.movement = movement(bot, position, threat, &arena);
+        }
+        let Some(target) = bot.target else {
+            input.movement = bot.movement;
+            tick_inputs.players.push(input);
+            continue;
+        };
+        // Where the target should be by now, going by when the bot last looked.
+        let expected = target.position + target.velocity * target.age;
+        let aim_point = lead_target(position, expected, target.velocity, bullet_config.speed);
+        input.aim = Vec2::from_angle(bot.aim_error)
+            .rotate(aim_point - position)
+            .normalize_or_zero();
+        input.movement = bot.movement;
+        tick_inputs.players.push(input);
+    }
+}
+
+/// The bullet that will hit the bot soonest, if it is seen coming: how many seconds away it
+/// is, how it is going and where it passes closest.
+fn incoming_bullet(
+    id: usize,
+    position: Vec2,
+    skill: BotSkill,
+    bullets: &Query<(&Transform, &Velocity, &ID), With<Bullet>>,
+    player_config: &PlayerConfig,
+    bullet_config: &BulletConfig,
+) -> Option<(f32, Vec2, Vec2)> {
+    let hit_distance = player_config.scale * 0.75 + bullet_config.scale / 2.0;
+    bullets
+        .iter()
+        .filter(|(_, _, owner)| owner.0 != id)
+        .filter_map(|(transform, velocity, _)| {
+            let start = transform.translation.truncate();
+            let speed_squared = velocity.length_squared();
+            if speed_squared == 0.0 {
+                return None;
+            }
+            let t = (position - start).dot(velocity.0) / speed_squared;
+            let closest = start + velocity.0 * t;
+            (t > 0.0 && t < skill.dodge_horizon && closest.distance(position) < hit_distance)
+                .then_some((t, velocity.0, closest))
+        })
+        .min_by(|(a, ..), (b, ..)| a.total_cmp(b))
+}
+
+/// The spot `margin` behind `wall`, as seen from `threat`.
+fn cover_behind(wall: &Transform, threat: Vec2, margin: f32) -> Vec2 {
+    let center = wall.translation.truncate();
+    let away = (center - threat).try_normalize().unwrap_or(Vec2::X);
+    // How far from the center the line away from the threat leaves the wall.
+    let reach = (wall.scale.truncate() / 2.0 / away.abs()).min_element();
+    center + away * (reach + margin)
+}
+
+/// Which way the bot's chosen action takes it.
+fn movement(bot: &Bot, position: Vec2, threat: Option<(f32, Vec2, Vec2)>, arena: &Arena) -> Vec2 {
+    let preferred_distance = bot.personality.preferred_distance;
+    let (towards, distance) = bot.target.map_or((Vec2::ZERO, 0.0), |target| {
+        let to_target = target.position - position;
+        (to_target.normalize_or_zero(), to_target.length())
+    });
+    let mut movement = match bot.action {
+        Some(Action::Chase) if distance > preferred_distance => towards,
+        Some(Action::Chase) => Vec2::ZERO,
+        Some(Action::Strafe) => {
+            let correction = if distance > preferred_distance + DISTANCE_TOLERANCE {
+                towards
+            } else if distance < preferred_distance - DISTANCE_TOLERANCE {
+                -towards
+            } else {
+                Vec2::ZERO
+            };
+            correction + towards.perp() * bot.strafe
+        }
+        Some(Action::Retreat) => -towards,
+        Some(Action::Dodge) => match threat {
+            Some((_, velocity, closest)) => {
+                let across = velocity.perp().normalize_or_zero();
+                match (position - closest).dot(across) >= 0.0 {
+                    true => across,
+                    false => -across,
+                }
+            }
+            None => Vec2::ZERO,
+        },
+        Some(Action::TakeCover) => match bot.cover {
+            Some(cover) if cover.distance(position) > COVER_TOLERANCE => {
+                (cover - position).normalize_or_zero()
+            }
+            _ => Vec2::ZERO,
+        },
+        Some(Action::Wander) => bot.wander,
+        None => Vec2::ZERO,
+    };
+    // Turn back from the edges rather than get pinned against them.
+    let room = arena.half_size - Vec2::splat(EDGE_MARGIN);
+    if position.x.abs() > room.x {
+        movement.x = -position.x.signum();
+    }
+    if position.y.abs() > room.y {
+        movement.y = -position.y.signum();
+    }
+    movement.normalize_or_zero()
+}
@@ -0,0 +1,361 @@
+//! The command line, for scripting game nights and test runs.
+
+use std::path::PathBuf;
+
+use bevy::prelude::*;
+
+use crate::{
+    arena::Map,
+    behavior::{Personality, DEFAULT_PERSONALITY},
+    bot::Difficulty,
+    rollback::MAX_PEERS,
+    settings::{Presets, Settings},
+    stats::Mode,
+};
+
+pub const USAGE: &str = "\
+Usage: hacker-wars [options]
+
+Options:
+  --window <WIDTH>x<HEIGHT>  window size [default: 1500x1000]
+  --fullscreen               start fullscreen
+  --preset <NAME>            start with a built-in or saved settings preset, or with
+                             --balance, a comma separated list of them to compare
+  --config <FILE>            start with the settings in a RON file, and apply edits to it live
+                             [default: hacker-wars.ron, if it exists and there's no
+                             --preset], or with --balance, a comma separated list of them to
+                             compare as well as any presets
+  --map <MAP>                the map to play on, open, with players spawning anywhere, or
+                             outposts, with players spawning around the edges and walls to
+                             take cover behind [default: open]
+  --mode <MODE>              the game mode [default: deathmatch]
+  --seed <NUMBER>            seed the random number generator, for repeatable matches
+  --headless                 simulate without a window, then print a report
+  --ticks <NUMBER>           how many ticks a headless run lasts [default: 3600]
+  --bots <NUMBER>            how many bots join the match [default: 2 when headless]
+  --difficulty <LIST>        how well the bots play, easy, normal or hard, taken in turn
+                             from a comma separated list [default: normal]
+  --personality <LIST>       how the bots play, taken in turn from a comma separated list of
+                             built-in or user personalities [default: balanced]
+  --replay <FILE>            play a replay file, or with --headless, run it as a script
+  --host <PORT>              let players on other machines join over the local network
+  --join <ADDRESS>           play in a match hosted at an address like 192.168.1.5:7777
+  --spectate                 watch without playing, locally or with --join, with the
+                             controllers steering the camera
+  --rollback <PORT>          play peer to peer with everyone in --peers, over this port
+  --peers <LIST>             the addresses of everyone in a --rollback match, comma
+                             separated in the same order on every machine, with this one
+                             as local, e.g. local,192.168.1.5:7777
+  --balance <NUMBER>         play this many headless bot matches with each preset and config
+                             file, and print win rates, time to kill and damage as CSV
+  --help                     show this message";
+
+#[derive(Debug, PartialEq)]
+pub struct Cli {
+    pub window_size: Vec2,
+    pub fullscreen: bool,
+    pub presets: Vec<String>,
+    pub configs: Vec<PathBuf>,
+    pub map: Map,
+    pub mode: Mode,
+    pub seed: Option<u64>,
+    pub headless: bool,
+    pub ticks: u32,
+    pub bots: Option<usize>,
+    pub difficulties: Vec<Difficulty>,
+    pub personalities: Vec<String>,
+    pub replay: Option<PathBuf>,
+    pub host: Option<u16>,
+    pub join: Option<String>,
+    pub spectate: bool,
+    pub rollback: Option<u16>,
+    /// The peers' addresses, with `None` for this machine.
+    pub peers: Vec<Option<String>>,
+    pub balance: Option<usize>,
+    pub help: bool,
+}
+
+impl Default for Cli {
+    fn default() -> Self {
+        Cli {
+            window_size: Vec2::new(1500.0, 1000.0),
+            fullscreen: false,
+            presets: vec![],
+            configs: vec![],
+            map: Map::default(),
+            mode: Mode::default(),
+            seed: None,
+            headless: false,
+            ticks: 60 * 60,
+            bots: None,
+            difficulties: vec![Difficulty::default()],
+            personalities: vec![DEFAULT_PERSONALITY.into()],
+            replay: None,
+            host: None,
+            join: None,
+            spectate: false,
+            rollback: None,
+            peers: vec![],
+            balance: None,
+            help: false,
+        }
+    }
+}
+
+impl Cli {
+    /// Parses the arguments following the program name.
+    pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Self, String> {
+        let mut cli = Cli::default();
+        let mut args = args.into_iter();
+        let mut bot_options = false;
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().ok_or_else(|| format!("{arg} needs a value"));
+            match arg.as_str() {
+                "--window" => cli.window_size = parse_window_size(&value()?)?,
+                "--fullscreen" => cli.fullscreen = true,
+                "--preset" => cli.presets = value()?.split(',').map(String::from).collect(),
+                "--config" => cli.configs = value()?.split(',').map(PathBuf::from).collect(),
+                "--map" => {
+                    let value = value()?;
+                    cli.map = Map::ALL
+                        .into_iter()
+                        .find(|map| map.to_string() == value)
+                        .ok_or_else(|| format!("--map is open or outposts, not {value}"))?;
+                }
+                "--mode" => {
+                    let value = value()?;
+                    cli.mode = Mode::ALL
+                        .into_iter()
+                        .find(|mode| mode.to_string() == value)
+                        .ok_or_else(|| format!("--mode is deathmatch, not {value}"))?;
+                }
+                "--seed" => cli.seed = Some(parse_number(&arg, &value()?)?),
+                "--headless" => cli.headless = true,
+                "--ticks" => cli.ticks = parse_number(&arg, &value()?)?,
+                "--bots" => cli.bots = Some(parse_number(&arg, &value()?)?),
+                "--difficulty" => {
+                    bot_options = true;
+                    cli.difficulties = value()?
+                        .split(',')
+                        .map(|value| {
+                            Difficulty::ALL
+                                .into_iter()
+                                .find(|difficulty| difficulty.to_string() == value)
+                                .ok_or_else(|| {
+                                    format!("--difficulty is easy, normal or hard, not {value}")
+                                })
+                        })
+                        .collect::<Result<_, _>>()?;
+                }
+                "--personality" => {
+                    bot_options = true;
+                    cli.personalities = value()?.split(',').map(String::from).collect();
+                }
+                "--replay" => cli.replay = Some(value()?.into()),
+                "--host" => cli.host = Some(parse_number(&arg, &value()?)?),
+                "--join" => cli.join = Some(value()?),
+                "--spectate" => cli.spectate = true,
+                "--rollback" => cli.rollback = Some(parse_number(&arg, &value()?)?),
+                "--peers" => {
+                    cli.peers = value()?
+                        .split(',')
+                        .map(|peer| (peer != "local").then(|| peer.to_string()))
+                        .collect();
+                }
+                "--balance" => cli.balance = Some(parse_number(&arg, &value()?)?),
+                "--help" | "-h" => cli.help = true,
+                _ => return Err(format!("unknown option {arg}")),
+            }
+        }
+
+        if bot_options && cli.bots.is_none() {
+            return Err("--difficulty and --personality are for the bots added with --bots".into());
+        }
+        if cli.presets.len() + cli.configs.len() > 1 && cli.balance.is_none() {
+            return Err(
+                "only --balance can compare more than one preset or config, or use both".into(),
+            );
+        }
+        if cli.replay.is_some() && (!cli.presets.is_empty() || !cli.configs.is_empty()) {
+            return Err(
+                "a replay brings its own settings, so --preset and --config can't be used with \
+                 --replay"
+                    .into(),
+            );
+        }
+        if cli.replay.is_some() && cli.seed.is_some() {
+            return Err(
+                "a replay brings its own seed, so --seed can't be used with --replay".into(),
+            );
+        }
+        if cli.replay.is_some() && cli.map != Map::default() {
+            return Err("a replay brings its own map, so --map can't be used with --replay".into());
+        }
+        if cli.bots.is_some() && cli.replay.is_some() {
+            return Err(
+                "a replay brings its own players, so --bots can't be used with --replay".into(),
+            );
+        }
+        if cli.balance.is_some() && cli.replay.is_some() {
+            return Err("--balance plays bot matches, so it can't be used with --replay".into());
+        }
+        if cli.host.is_some() && (cli.replay.is_some() || cli.balance.is_some()) {
+            return Err("--host can't be used with --replay or --balance".into());
+        }
+        if cli.join.is_some()
+            && (cli.host.is_some()
+                || cli.headless
+                || cli.replay.is_some()
+                || cli.balance.is_some()
+                || cli.bots.is_some()
+                || cli.seed.is_some()
+                || !cli.presets.is_empty()
+                || !cli.configs.is_empty()
+                || cli.map != Map::default())
+        {
+            return Err(
+                "the host runs the match, so only --window, --fullscreen and --spectate can be \
+                 used with --join"
+                    .into(),
+            );
+        }
+        if cli.spectate && (cli.headless || cli.balance.is_some() || cli.rollback.is_some()) {
+            return Err(
+                "there is nothing to watch with --headless or --balance, and every --rollback \
+                 peer plays, so they can't be used with --spectate"
+                    .into(),
+            );
+        }
+        if cli.rollback.is_some() == cli.peers.is_empty() {
+            return Err("--rollback and --peers go together".into());
+        }
+        if !cli.peers.is_empty()
+            && (!(2..=MAX_PEERS).contains(&cli.peers.len())
+                || cli.peers.iter().filter(|peer| peer.is_none()).count() != 1)
+        {
+            return Err(format!(
+                "--peers lists 2 to {MAX_PEERS} machines, one of them local"
+            ));
+        }
+        if cli.rollback.is_some()
+            && (cli.host.is_some()
+                || cli.join.is_some()
+                || cli.headless
+                || cli.replay.is_some()
+                || cli.balance.is_some()
+                || cli.bots.is_some()
+                || !cli.configs.is_empty())
+        {
+            return Err(
+                "every peer plays the same match, so only --window, --fullscreen, --preset, \
+                 --map and --seed can be used with --rollback"
+                    .into(),
+            );
+        }
+        Ok(cli)
+    }
+
+    /// The difficulty and personality of each bot to add.
+    pub fn bots(&self) -> Vec<(Difficulty, String)> {
+        let count = self
+            .bots
+            .unwrap_or(if self.is_simulation() { 2 } else { 0 });
+        (0..count)
+            .map(|i| {
+                (
+                    self.difficulties[i % self.difficulties.len()],
+                    self.personalities[i % self.personalities.len()].clone(),
+                )
+            })
+            .collect()
+    }
+
+    /// The personalities bots can have. Like the settings, headless runs only use the built-in
+    /// ones, plus any others of the user's that `--personality` names.
+    pub fn personalities(&self) -> Vec<(String, Personality)> {
+        if !self.is_simulation() {
+            return Personality::load_all();
+        }
+        let builtin = Personality::builtin();
+        let is_builtin = |name: &String| builtin.iter().any(|(builtin, _)| builtin == name);
+        if self.personalities.iter().all(is_builtin) {
+            return builtin;
+        }
+        let user = Personality::load_all();
+        let mut personalities = builtin;
+        for name in &self.personalities {
+            if personalities.iter().any(|(existing, _)| existing == name) {
+                continue;
+            }
+            if let Some(personality) = user.iter().find(|(user, _)| user == name) {
+                personalities.push(personality.clone());
+            }
+        }
+        personalities
+    }
+
+    /// Whether bots play a match without a window, rather than someone watching or scripting it.
+    fn is_simulation(&self) -> bool {
+        (self.headless || self.balance.is_some()) && self.replay.is_none()
+    }
+
+    /// The settings to start with.
+    pub fn settings(&self) -> Result<Settings, String> {
+        Ok(self.variants()?.swap_remove(0).1)
+    }
+
+    /// The named settings to play with, one for each preset and then each config file listed.
+    /// Headless runs are for testing and balancing, and peers have to agree, so they don't
+    /// depend on what was last played.
+    pub fn variants(&self) -> Result<Vec<(String, Settings)>, String> {
+        let mut variants: Vec<(String, Settings)> = vec![];
+        if !self.presets.is_empty() {
+            let presets = Presets::load().presets;
+            for name in &self.presets {
+                let preset = presets
+                    .iter()
+                    .find(|(preset, _)| preset == name)
+                    .cloned()
+                    .ok_or_else(|| format!("there is no preset called {name}"))?;
+                variants.push(preset);
+            }
+        }
+        for path in &self.configs {
+            let settings =
+                Settings::load(path).map_err(|err| format!("{}: {err}", path.display()))?;
+            variants.push((path.display().to_string(), settings));
+        }
+        if variants.is_empty() {
+            variants.push(
+                if self.headless || self.balance.is_some() || self.rollback.is_some() {
+                    ("Classic".into(), Settings::classic())
+                } else {
+                    ("saved".into(), Settings::load_saved())
+                },
+            );
+        }
+        for (name, settings) in &variants {
+            settings
+                .validate()
+                .map_err(|err| format!("{name}: {err}"))?;
+        }
+        Ok(variants)
+    }
+}
+
+fn parse_window_size(value: &str) -> Result<Vec2, String> {
+    let size = value
+        .split_once('x')
+        .and_then(|(width, height)| Some((width.parse().ok()?, height.parse().ok()?)))
+        .filter(|&(width, height): &(u32, u32)| width > 0 && height > 0);
+    match size {
+        Some((width, height)) => Ok(Vec2::new(width as f32, height as f32)),
+        None => Err(format!("--window takes a size like 1500x1000, not {value}")),
+    }
+}
+
+fn parse_number<T: std::str::FromStr>(option: &str, value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("{option} takes a number, not {value}"))
+}
@@ -0,0 +1,340 @@
+//! Shooting: spawning bullets, moving them, and bullets hitting players and each other.
+
+use std::{cmp::Ordering, f32::consts::PI};
+
+use bevy::{
+    prelude::*,
+    sprite::{collide_aabb::collide, MaterialMesh2dBundle},
+    utils::HashSet,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    arena::{Arena, Wall},
+    input::TickInputs,
+    player::{Alive, Health, Player, ID},
+    GameSet,
+};
+
+/// How many times per second a spawn protected player blinks.
+const SPAWN_PROTECTION_FLASHES_PER_SECOND: f32 = 6.0;
+
+pub struct CombatPlugin;
+
+impl Plugin for CombatPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<BulletConfig>()
+            .init_resource::<BulletMesh>()
+            .register_type::<BulletMesh>()
+            .register_type::<Bullet>()
+            .register_type::<Collider>()
+            .register_type::<Velocity>()
+            .register_type::<Shooter>()
+            .register_type::<SpawnProtection>()
+            .add_event::<PlayerShot>()
+            .add_event::<PlayerHit>()
+            .add_event::<PlayerDied>()
+            .add_systems(Startup, setup_bullet_mesh)
+            .add_systems(
+                FixedUpdate,
+                (
+                    tick_spawn_protection,
+                    create_bullets.after(tick_spawn_protection),
+                    apply_velocity,
+                    despawn_bullets.after(apply_velocity),
+                    check_for_collisions
+                        .after(apply_velocity)
+                        .after(create_bullets),
+                    // bounce_bullets,
+                )
+                    .in_set(GameSet::Combat),
+            )
+            .add_systems(Update, flash_spawn_protection);
+    }
+}
+
+pub fn setup_bullet_mesh(mut meshes: ResMut<Assets<Mesh>>, mut bullet_mesh: ResMut<BulletMesh>) {
+    bullet_mesh.mesh_handle = meshes.add(shape::Circle::default().into());
+}
+
+/// A player fired a bullet.
+#[derive(Event, Default)]
+pub struct PlayerShot {
+    pub shooter: usize,
+}
+
+/// A bullet took one health off a player.
+#[derive(Event, Default)]
+pub struct PlayerHit {
+    pub victim: usize,
+    pub shooter: usize,
+}
+
+#[derive(Event, Clone, Copy, Default, PartialEq, Debug)]
+pub struct PlayerDied {
+    pub victim: usize,
+    /// Whose bullet it was, or `None` if the victim took themselves out.
+    pub killer: Option<usize>,
+}
+
+#[derive(Resource, Default, Reflect)]
+#[reflect(Resource)]
+pub struct BulletMesh {
+    pub mesh_handle: Handle<Mesh>,
+}
+
+#[derive(Resource, Default, Reflect, Clone, PartialEq, Debug, Serialize, Deserialize)]
+#[reflect(Resource)]
+pub struct BulletConfig {
+    pub speed: f32,
+    pub collide: bool,
+    pub scale: f32,
+}
+
+#[derive(Component, Default, Reflect, Clone, Deref, DerefMut)]
+#[reflect(Component)]
+pub struct Velocity(pub Vec2);
+
+#[derive(Component, Default, Reflect, Clone)]
+#[reflect(Component)]
+pub struct Shooter {
+    pub timer: Timer,
+}
+
+#[derive(Component, Default, Reflect)]
+#[reflect(Component)]
+pub struct Bullet;
+
+#[derive(Component, Default, Reflect)]
+#[reflect(Component)]
+pub struct Collider;
+
+/// Bullets pass through a freshly spawned player until the timer runs out or they fire.
+#[derive(Component, Default, Reflect, Clone)]
+#[reflect(Component)]
+pub struct SpawnProtection {
+    pub timer: Timer,
+}
+
+impl SpawnProtection {
+    pub fn new(seconds: f32) -> Self {
+        SpawnProtection {
+            timer: Timer::from_seconds(seconds, TimerMode::Once),
+        }
+    }
+}
+
+pub fn tick_spawn_protection(
+    mut protected: Query<(Entity, &mut SpawnProtection)>,
+    fixed_time: Res<FixedTime>,
+    mut commands: Commands,
+) {
+    for (entity, mut protection) in &mut protected {
+        protection.timer.tick(fixed_time.period);
+        if protection.timer.finished() {
+            commands.entity(entity).remove::<SpawnProtection>();
+        }
+    }
+}
+
+pub fn flash_spawn_protection(
+    mut players: Query<(&mut Visibility, Option<&SpawnProtection>), (With<Player>, With<Alive>)>,
+) {
+    for (mut visibility, protection) in &mut players {
+        let shown = protection.map_or(true, |protection| {
+            let flashes = protection.timer.elapsed_secs() * SPAWN_PROTECTION_FLASHES_PER_SECOND;
+            flashes.fract() < 0.5
+        });
+        visibility.set_if_neq(if shown {
+            Visibility::Inherited
+        } else {
+            Visibility::Hidden
+        });
+    }
+}
+
+pub fn create_bullets(
+    mut commands: Commands,
+    bullet_mesh: Res<BulletMesh>,
+    mut players: Query<
+        (
+            Entity,
+            &Transform,
+            &ID,
+            &Player,
+            &mut Shooter,
+            Option<&SpawnProtection>,
+        ),
+        With<Alive>,
+    >,
+    tick_inputs: Res<TickInputs>,
+    fixed_time: Res<FixedTime>,
+    bullet_config: Res<BulletConfig>,
+    mut ev_player_shot: EventWriter<PlayerShot>,
+) {
+    for (entity, transform, id, player, mut shooter, protection) in &mut players {
+        shooter.timer.tick(fixed_time.period);
+
+        if shooter.timer.just_finished() {
+            if protection.is_some() {
+                // Protected players hold their fire until they aim, and firing ends protection.
+                let aiming = tick_inputs
+                    .player(id.0)
+                    .is_some_and(|input| input.aim != Vec2::ZERO);
+                if !aiming {
+                    continue;
+                }
+                commands.entity(entity).remove::<SpawnProtection>();
+            }
+            let (v, mut angle) = transform.rotation.to_axis_angle();
+            angle *= v.z;
+            angle += PI / 2.0;
+            let mut bullet_commands = commands.spawn((
+                MaterialMesh2dBundle {
+                    mesh: bullet_mesh.mesh_handle.clone().into(),
+                    material: player.material_handle.clone(),
+                    transform: Transform::from_translation(transform.translation)
+                        .with_scale(Vec3::new(bullet_config.scale, bullet_config.scale, 0.0)),
+                    ..default()
+                },
+                Bullet,
+                ID(id.0),
+                Velocity(Vec2::from_angle(angle).rotate(Vec2::X) * bullet_config.speed),
+                Name::new("Bullet"),
+            ));
+            if bullet_config.collide {
+                bullet_commands.insert(Collider);
+            }
+            ev_player_shot.send(PlayerShot { shooter: id.0 });
+        }
+    }
+}
+
+pub fn apply_velocity(mut query: Query<(&mut Transform, &Velocity)>, fixed_time: Res<FixedTime>) {
+    for (mut transform, velocity) in &mut query {
+        transform.translation.x += velocity.x * fixed_time.period.as_secs_f32();
+        transform.translation.y += velocity.y * fixed_time.period.as_secs_f32();
+    }
+}
+
+// fn bounce_bullets(
+//     mut query: Query<(&Transform, &mut Velocity), With<Bullet>>,
+//     windows: Query<&Window>,
+// ) {
+//     let window = windows.single();
+//     for (transform, mut velocity) in &mut query {
+//         if transform.translation.x < -window.width() / 2.0
+//             || transform.translation.x > window.width() / 2.0
+//         {
+//             velocity.x = -velocity.x;
+//         }
+//         if transform.translation.y < -window.height() / 2.0
+//             || transform.translation.y > window.height() / 2.0
+//         {
+//             velocity.y = -velocity.y;
+//         }
+//     }
+// }
+
+/// Bullets stop at the edges of the arena and at walls.
+pub fn despawn_bullets(
+    mut query: Query<(Entity, &Transform), With<Bullet>>,
+    walls: Query<&Transform, (With<Wall>, Without<Bullet>)>,
+    arena: Res<Arena>,
+    mut commands: Commands,
+) {
+    for (entity, transform) in &mut query {
+        let in_wall = walls.iter().any(|wall| {
+            collide(
+                wall.translation,
+                wall.scale.truncate(),
+                transform.translation,
+                transform.scale.truncate(),
+            )
+            .is_some()
+        });
+        if in_wall || !arena.contains(transform.translation.truncate()) {
+            commands.entity(entity).despawn();
+        }
+    }
+}
+
+pub fn check_for_collisions(
+    bullet_query: Query<(Entity, &ID, &Transform), With<Bullet>>,
+    mut hit_query: Query<
+        (Entity, &ID, &Transform, Option<&mut Health>),
+        (With<Collider>, Without<SpawnProtection>),
+    >,
+    mut commands: Commands,
+    mut ev_player_hit: EventWriter<PlayerHit>,
+    mut ev_player_died: EventWriter<PlayerDied>,
+) {
+    // In an order that depends only on where everything is, not on how the entities happen to
+    // be stored, so that hits resolve the same on every peer of a rollback session.
+    let mut bullets: Vec<(Entity, usize, Vec3, Vec3)> = bullet_query
+        .iter()
+        .map(|(entity, id, transform)| (entity, id.0, transform.translation, transform.scale))
+        .collect();
+    bullets.sort_by(|a, b| by_id_and_position((a.1, a.2), (b.1, b.2)));
+    let mut targets: Vec<(Entity, usize, Vec3, Vec3)> = hit_query
+        .iter()
+        .map(|(entity, id, transform, _)| (entity, id.0, transform.translation, transform.scale))
+        .collect();
+    targets.sort_by(|a, b| by_id_and_position((a.1, a.2), (b.1, b.2)));
+
+    let mut bullets_despawned = HashSet::new();
+    for (bullet_entity, bullet_id, bullet_translation, bullet_scale) in bullets {
+        if bullets_despawned.contains(&bullet_entity) {
+            continue;
+        }
+        for &(hit_entity, hit_id, hit_translation, hit_scale) in &targets {
+            if bullets_despawned.contains(&hit_entity) {
+                continue;
+            }
+            if hit_id == bullet_id {
+                continue;
+            }
+            let collision = collide(
+                hit_translation,
+                hit_scale.truncate(),
+                bullet_translation,
+                bullet_scale.truncate(),
+            );
+            if collision.is_some() {
+                commands.entity(bullet_entity).despawn();
+                bullets_despawned.insert(bullet_entity);
+                let player_health = hit_query
+                    .get_mut(hit_entity)
+                    .ok()
+                    .and_then(|(.., health)| health);
+                match player_health {
+                    Some(mut player_health) => {
+                        player_health.current_health -= 1;
+                        ev_player_hit.send(PlayerHit {
+                            victim: hit_id,
+                            shooter: bullet_id,
+                        });
+                        if player_health.current_health == 0 {
+                            ev_player_died.send(PlayerDied {
+                                victim: hit_id,
+                                killer: Some(bullet_id),
+                            });
+                        }
+                    }
+                    None => {
+                        commands.entity(hit_entity).despawn();
+                        bullets_despawned.insert(hit_entity);
+                    }
+                }
+                break;
+            }
+        }
+    }
+}
+
+/// Orders by ID, then by position.
+fn by_id_and_position(a: (usize, Vec3), b: (usize, Vec3)) -> Ordering {
+    a.0.cmp(&b.0)
+        .then(a.1.x.total_cmp(&b.1.x))
+        .then(a.1.y.total_cmp(&b.1.y))
+}
@@ -0,0 +1,134 @@
+//! Watches a config file, `hacker-wars.ron` by default, and applies it whenever it changes
+//! while the game runs. A file that doesn't parse or has values out of range is reported on
+//! screen and otherwise ignored until it is fixed.
+
+use std::{fs, io, path::PathBuf};
+
+use bevy::prelude::*;
+
+use crate::{combat::BulletConfig, player::PlayerConfig, settings::Settings};
+
+pub const DEFAULT_CONFIG_FILE: &str = "hacker-wars.ron";
+/// How often the file is checked for changes.
+const POLL_SECONDS: f32 = 0.5;
+
+pub struct ConfigFilePlugin {
+    pub path: PathBuf,
+}
+
+impl Default for ConfigFilePlugin {
+    fn default() -> Self {
+        ConfigFilePlugin {
+            path: PathBuf::from(DEFAULT_CONFIG_FILE),
+        }
+    }
+}
+
+impl Plugin for ConfigFilePlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(ConfigFile {
+            path: self.path.clone(),
+            poll: Timer::from_seconds(POLL_SECONDS, TimerMode::Repeating),
+            read: false,
+            contents: None,
+            error: None,
+        })
+        .add_systems(Startup, setup_config_file_error)
+        .add_systems(Update, (reload_config_file, show_config_file_error).chain());
+    }
+}
+
+#[derive(Resource)]
+pub struct ConfigFile {
+    pub path: PathBuf,
+    poll: Timer,
+    /// Whether the file has been read yet. It is read on the first frame without waiting.
+    read: bool,
+    /// What the file held when it was last read, to tell when it changes.
+    contents: Option<String>,
+    /// Why the file as it is now can't be applied.
+    pub error: Option<String>,
+}
+
+#[derive(Component)]
+pub struct ConfigFileErrorText;
+
+pub fn setup_config_file_error(mut commands: Commands) {
+    commands.spawn((
+        TextBundle::from_section(
+            "",
+            TextStyle {
+                font_size: 20.0,
+                color: Color::rgb(1.0, 0.3, 0.3),
+                ..default()
+            },
+        )
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            top: Val::Px(10.0),
+            left: Val::Px(10.0),
+            ..default()
+        }),
+        ConfigFileErrorText,
+        Name::new("Config file error"),
+    ));
+}
+
+pub fn reload_config_file(
+    mut config_file: ResMut<ConfigFile>,
+    mut player_config: ResMut<PlayerConfig>,
+    mut bullet_config: ResMut<BulletConfig>,
+    time: Res<Time>,
+) {
+    // Real time, so that the file is still picked up while the game is paused.
+    if !config_file.poll.tick(time.raw_delta()).just_finished() && config_file.read {
+        return;
+    }
+    config_file.read = true;
+    let contents = match fs::read_to_string(&config_file.path) {
+        Ok(contents) => Some(contents),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => None,
+        Err(err) => {
+            config_file.error = Some(err.to_string());
+            return;
+        }
+    };
+    if contents == config_file.contents {
+        return;
+    }
+    config_file.contents = contents.clone();
+    let Some(contents) = contents else {
+        config_file.error = None;
+        return;
+    };
+    let settings = ron::from_str::<Settings>(&contents)
+        .map_err(|err| err.to_string())
+        .and_then(|settings| settings.validate().map(|()| settings));
+    match settings {
+        Ok(settings) => {
+            info!("applying {}", config_file.path.display());
+            config_file.error = None;
+            *player_config = settings.player_config;
+            *bullet_config = settings.bullet_config;
+        }
+        Err(err) => {
+            warn!("not applying {}: {err}", config_file.path.display());
+            config_file.error = Some(err);
+        }
+    }
+}
+
+pub fn show_config_file_error(
+    config_file: Res<ConfigFile>,
+    mut texts: Query<&mut Text, With<ConfigFileErrorText>>,
+) {
+    if !config_file.is_changed() {
+        return;
+    }
+    for mut text in &mut texts {
+        text.sections[0].value = match &config_file.error {
+            Some(error) => format!("{}: {error}", config_file.path.display()),
+            None => String::new(),
+        };
+    }
+}
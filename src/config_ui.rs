@@ -0,0 +1,326 @@
+//! The settings window, toggled with Escape. It isn't shown while a replay plays, since the
+//! settings are part of what was recorded.
+
+use bevy::{input::common_conditions::input_toggle_active, prelude::*};
+use std::ops::RangeInclusive;
+
+use bevy_inspector_egui::{
+    bevy_egui::EguiContexts,
+    egui::{emath::Numeric, CollapsingHeader, ComboBox, Slider, Ui},
+};
+
+use crate::{
+    behavior::DEFAULT_PERSONALITY,
+    bot::{Bots, Difficulty},
+    combat::BulletConfig,
+    input::PendingInputs,
+    lobby::PlayerName,
+    player::{Player, PlayerConfig, PlayerOverrides, ID},
+    replay::is_playing_back,
+    settings::{
+        Presets, Settings, BULLET_SCALE_RANGE, BULLET_SPEED_RANGE, HEALTH_RANGE,
+        MIN_SPAWN_DISTANCE_RANGE, RECONNECT_GRACE_RANGE, SCALE_RANGE, SHOOTING_DELAY_RANGE,
+        SPAWN_PROTECTION_RANGE, SPEED_RANGE, TURNING_SPEED_RANGE,
+    },
+};
+
+/// Requires `EguiPlugin`.
+pub struct ConfigUiPlugin;
+
+impl Plugin for ConfigUiPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            config_ui_system
+                .run_if(input_toggle_active(true, KeyCode::Escape))
+                .run_if(not(is_playing_back)),
+        );
+    }
+}
+
+pub enum SettingValue<'a> {
+    Float {
+        value: &'a mut f32,
+        range: RangeInclusive<f32>,
+        step: f32,
+    },
+    Int {
+        value: &'a mut i32,
+        range: RangeInclusive<i32>,
+        step: i32,
+    },
+    Bool(&'a mut bool),
+}
+
+pub struct Setting<'a> {
+    pub label: &'static str,
+    pub value: SettingValue<'a>,
+}
+
+pub const SETTING_COUNT: usize = 12;
+
+/// The settings in the order they are listed in the settings window and the pause menu, with
+/// how far each can be set, for changing them. `None` past the last one.
+pub fn setting_mut<'a>(
+    index: usize,
+    player_config: &'a mut PlayerConfig,
+    bullet_config: &'a mut BulletConfig,
+) -> Option<Setting<'a>> {
+    let float = |value, range, step| SettingValue::Float { value, range, step };
+    let (label, value) = match index {
+        0 => (
+            "player speed",
+            float(&mut player_config.speed, SPEED_RANGE, 25.0),
+        ),
+        1 => (
+            "turning speed",
+            float(&mut player_config.turning_speed, TURNING_SPEED_RANGE, 1.0),
+        ),
+        2 => (
+            "shooting delay",
+            float(
+                &mut player_config.shooting_delay,
+                SHOOTING_DELAY_RANGE,
+                0.05,
+            ),
+        ),
+        3 => (
+            "player size",
+            float(&mut player_config.scale, SCALE_RANGE, 5.0),
+        ),
+        4 => (
+            "invincible",
+            SettingValue::Bool(&mut player_config.invincible),
+        ),
+        5 => (
+            "health",
+            SettingValue::Int {
+                value: &mut player_config.starting_health,
+                range: HEALTH_RANGE,
+                step: 1,
+            },
+        ),
+        6 => (
+            "min spawn distance",
+            float(
+                &mut player_config.min_spawn_distance,
+                MIN_SPAWN_DISTANCE_RANGE,
+                50.0,
+            ),
+        ),
+        7 => (
+            "spawn protection",
+            float(
+                &mut player_config.spawn_protection,
+                SPAWN_PROTECTION_RANGE,
+                0.5,
+            ),
+        ),
+        8 => (
+            "reconnect grace",
+            float(
+                &mut player_config.reconnect_grace,
+                RECONNECT_GRACE_RANGE,
+                5.0,
+            ),
+        ),
+        9 => (
+            "bullet speed",
+            float(&mut bullet_config.speed, BULLET_SPEED_RANGE, 50.0),
+        ),
+        10 => (
+            "bullets collide",
+            SettingValue::Bool(&mut bullet_config.collide),
+        ),
+        11 => (
+            "bullet size",
+            float(&mut bullet_config.scale, BULLET_SCALE_RANGE, 1.0),
+        ),
+        _ => return None,
+    };
+    Some(Setting { label, value })
+}
+
+/// Reads the setting at `index` without touching the configs, by way of copies of them.
+/// `None` past the last one.
+pub fn read_setting<T>(
+    index: usize,
+    player_config: &PlayerConfig,
+    bullet_config: &BulletConfig,
+    read: impl FnOnce(&Setting) -> T,
+) -> Option<T> {
+    let (mut player_config, mut bullet_config) = (player_config.clone(), bullet_config.clone());
+    setting_mut(index, &mut player_config, &mut bullet_config).map(|setting| read(&setting))
+}
+
+pub fn config_ui_system(
+    mut contexts: EguiContexts,
+    mut player_config: ResMut<PlayerConfig>,
+    mut bullet_config: ResMut<BulletConfig>,
+    mut pending_inputs: ResMut<PendingInputs>,
+    players: Query<(&ID, &PlayerName, Option<&PlayerOverrides>), With<Player>>,
+    presets: Option<ResMut<Presets>>,
+    bots: Option<ResMut<Bots>>,
+    mut preset_name: Local<String>,
+    mut preset_status: Local<String>,
+    mut personality: Local<Option<String>>,
+) {
+    bevy_inspector_egui::egui::Window::new("Settings").show(contexts.ctx_mut(), |ui| {
+        if let Some(mut presets) = presets {
+            ui.collapsing("Presets", |ui| {
+                ui.horizontal_wrapped(|ui| {
+                    for (name, settings) in &presets.presets {
+                        if ui.button(name).clicked() {
+                            *player_config = settings.player_config.clone();
+                            *bullet_config = settings.bullet_config.clone();
+                            *preset_status = format!("loaded {name}");
+                        }
+                    }
+                });
+                ui.horizontal(|ui| {
+                    ui.text_edit_singleline(&mut *preset_name);
+                    if ui.button("Save preset").clicked() {
+                        let settings = Settings {
+                            player_config: player_config.clone(),
+                            bullet_config: bullet_config.clone(),
+                        };
+                        *preset_status = match presets.save(preset_name.trim(), settings) {
+                            Ok(()) => format!("saved {}", preset_name.trim()),
+                            Err(err) => format!("couldn't save: {err}"),
+                        };
+                    }
+                });
+                if !preset_status.is_empty() {
+                    ui.label(&*preset_status);
+                }
+            });
+        }
+        if let Some(mut bots) = bots {
+            ui.collapsing("Bots", |ui| {
+                let personality =
+                    personality.get_or_insert_with(|| DEFAULT_PERSONALITY.to_string());
+                ComboBox::from_label("personality")
+                    .selected_text(personality.as_str())
+                    .show_ui(ui, |ui| {
+                        for (name, _) in &bots.personalities {
+                            ui.selectable_value(personality, name.clone(), name);
+                        }
+                    });
+                ui.horizontal(|ui| {
+                    for difficulty in Difficulty::ALL {
+                        if ui.button(format!("Add {difficulty} bot")).clicked() {
+                            // The list only offers personalities that exist.
+                            let _ = bots.add(difficulty, personality);
+                        }
+                    }
+                });
+                let mut removed = None;
+                for bot in bots.playing() {
+                    ui.horizontal(|ui| {
+                        ui.label(format!(
+                            "Bot {}: {} ({})",
+                            bot.id, bot.personality_name, bot.difficulty
+                        ));
+                        if ui.button("Remove").clicked() {
+                            removed = Some(bot.id);
+                        }
+                    });
+                }
+                if let Some(id) = removed {
+                    bots.remove(id);
+                }
+            });
+        }
+        for index in 0..SETTING_COUNT {
+            let Some(Setting { label, value }) =
+                setting_mut(index, &mut player_config, &mut bullet_config)
+            else {
+                break;
+            };
+            match value {
+                SettingValue::Float { value, range, .. } => {
+                    ui.add(Slider::new(value, range).text(label));
+                }
+                SettingValue::Int { value, range, .. } => {
+                    ui.add(Slider::new(value, range).text(label));
+                }
+                SettingValue::Bool(value) => {
+                    ui.checkbox(value, label);
+                }
+            }
+        }
+
+        let mut players: Vec<_> = players.iter().collect();
+        players.sort_by_key(|(id, _, _)| id.0);
+        for (id, name, overrides) in players {
+            // Show edits that haven't reached the simulation yet so sliders don't jump back.
+            let mut overrides = pending_inputs
+                .player_overrides(id.0)
+                .or(overrides)
+                .cloned()
+                .unwrap_or_default();
+            let mut changed = false;
+            // Names can change and repeat, so the ID keeps each player's section open or shut.
+            CollapsingHeader::new(name.as_str())
+                .id_source(id.0)
+                .show(ui, |ui| {
+                    changed |= override_slider(
+                        ui,
+                        &mut overrides.speed,
+                        player_config.speed,
+                        SPEED_RANGE,
+                        "player speed",
+                    );
+                    changed |= override_slider(
+                        ui,
+                        &mut overrides.shooting_delay,
+                        player_config.shooting_delay,
+                        SHOOTING_DELAY_RANGE,
+                        "shooting delay",
+                    );
+                    changed |= override_slider(
+                        ui,
+                        &mut overrides.scale,
+                        player_config.scale,
+                        SCALE_RANGE,
+                        "player size",
+                    );
+                    changed |= override_slider(
+                        ui,
+                        &mut overrides.starting_health,
+                        player_config.starting_health,
+                        HEALTH_RANGE,
+                        "health",
+                    );
+                });
+            if changed {
+                pending_inputs.player_overrides.push((id.0, overrides));
+            }
+        }
+    });
+}
+
+/// A checkbox to override a setting for one player, and a slider for the overriding value.
+fn override_slider<T: Numeric>(
+    ui: &mut Ui,
+    value: &mut Option<T>,
+    default: T,
+    range: RangeInclusive<T>,
+    text: &str,
+) -> bool {
+    ui.horizontal(|ui| {
+        let mut overridden = value.is_some();
+        let mut changed = ui.checkbox(&mut overridden, "").changed();
+        let mut current = value.unwrap_or(default);
+        if overridden {
+            changed |= ui
+                .add(Slider::new(&mut current, range).text(text))
+                .changed();
+        } else {
+            ui.label(text);
+        }
+        *value = overridden.then_some(current);
+        changed
+    })
+    .inner
+}
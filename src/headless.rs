@@ -0,0 +1,185 @@
+//! Runs the gameplay simulation without a window or renderer, driven by scripted or bot
+//! inputs, either for a fixed number of ticks before printing a report and exiting, or for as
+//! long as whoever updates the app likes.
+
+use bevy::{app::AppExit, prelude::*, time::TimeUpdateStrategy};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::{
+    bot::{drive_bots, BotPlugin, Bots},
+    input::TickInputs,
+    player::{Alive, Controller, Health, Player, ID},
+    replay::Replay,
+    stats::{track_match_stats, MatchStats},
+    GameRng, GameSet,
+};
+
+/// The arena used when there is no window to take the size from, or when players' windows
+/// may not agree on one.
+pub const HEADLESS_ARENA_HALF_SIZE: Vec2 = Vec2::new(750.0, 500.0);
+
+pub enum HeadlessInputs {
+    /// Feed the recorded inputs of a replay, using its seed and configs.
+    Script(Replay),
+    /// Play with these bots, which join on the first tick.
+    Bots(Box<Bots>),
+}
+
+pub struct HeadlessPlugin {
+    pub inputs: HeadlessInputs,
+    /// How many ticks to run before printing a report and exiting, or `None` to leave that to
+    /// whoever updates the app.
+    pub ticks: Option<u32>,
+    pub seed: Option<u64>,
+}
+
+impl Plugin for HeadlessPlugin {
+    fn build(&self, app: &mut App) {
+        let period = FixedTime::default().period;
+        app.add_plugins((MinimalPlugins, AssetPlugin::default()))
+            .add_asset::<Mesh>()
+            .add_asset::<ColorMaterial>()
+            // Every update advances the clock by exactly one tick, as fast as possible.
+            .insert_resource(TimeUpdateStrategy::ManualDuration(period));
+        if let Some(ticks) = self.ticks {
+            app.insert_resource(TickLimit {
+                ticks: 0,
+                limit: ticks,
+            })
+            .init_resource::<RunStats>()
+            .add_systems(
+                FixedUpdate,
+                (add_up_run_stats, finish_simulation)
+                    .chain()
+                    .after(track_match_stats),
+            );
+        }
+
+        let seed = self.seed.unwrap_or_else(|| rand::thread_rng().gen());
+        match &self.inputs {
+            HeadlessInputs::Script(replay) => {
+                let replay = replay.clone();
+                app.insert_resource(GameRng(StdRng::seed_from_u64(replay.seed)))
+                    .insert_resource(FixedTime::new(replay.tick_period))
+                    .insert_resource(TimeUpdateStrategy::ManualDuration(replay.tick_period))
+                    .insert_resource(replay.player_config.clone())
+                    .insert_resource(replay.bullet_config.clone())
+                    .insert_resource(replay.map)
+                    .insert_resource(ScriptedInputs {
+                        ticks: replay.ticks,
+                        cursor: 0,
+                    })
+                    .add_systems(
+                        FixedUpdate,
+                        read_scripted_inputs.in_set(GameSet::ReadInputs),
+                    );
+            }
+            HeadlessInputs::Bots(bots) => {
+                app.add_plugins(BotPlugin)
+                    .insert_resource(GameRng(StdRng::seed_from_u64(seed)))
+                    .insert_resource(Bots::clone(bots))
+                    .add_systems(
+                        FixedUpdate,
+                        clear_tick_inputs
+                            .in_set(GameSet::ReadInputs)
+                            .before(drive_bots),
+                    );
+            }
+        }
+    }
+}
+
+#[derive(Resource)]
+struct ScriptedInputs {
+    ticks: Vec<TickInputs>,
+    cursor: usize,
+}
+
+fn read_scripted_inputs(mut script: ResMut<ScriptedInputs>, mut tick_inputs: ResMut<TickInputs>) {
+    *tick_inputs = script.ticks.get(script.cursor).cloned().unwrap_or_default();
+    script.cursor += 1;
+}
+
+/// Starts every tick with no inputs but the bots', and the arena on the first.
+pub fn clear_tick_inputs(mut tick_inputs: ResMut<TickInputs>, mut first: Local<bool>) {
+    *tick_inputs = TickInputs {
+        arena: (!*first).then_some(HEADLESS_ARENA_HALF_SIZE),
+        ..default()
+    };
+    *first = true;
+}
+
+/// How many ticks the run lasts, and how many it has so far.
+#[derive(Resource)]
+struct TickLimit {
+    ticks: u32,
+    limit: u32,
+}
+
+/// The stats of every match finished so far, since [`MatchStats`] start again with each match.
+#[derive(Resource, Default)]
+struct RunStats {
+    finished: MatchStats,
+    /// Whether the match in [`MatchStats`] has finished and been added in.
+    added: bool,
+}
+
+fn add_up_run_stats(
+    stats: Res<MatchStats>,
+    mut run_stats: ResMut<RunStats>,
+    mut match_on: Local<bool>,
+) {
+    let was_on = std::mem::replace(&mut *match_on, stats.match_on);
+    if stats.match_on && !was_on {
+        run_stats.added = false;
+    }
+    if was_on && !stats.match_on {
+        run_stats.finished.add(&stats);
+        run_stats.added = true;
+    }
+}
+
+fn finish_simulation(
+    stats: Res<MatchStats>,
+    run_stats: Res<RunStats>,
+    mut tick_limit: ResMut<TickLimit>,
+    fixed_time: Res<FixedTime>,
+    players: Query<(&ID, &Health, Option<&Controller>, Option<&Alive>), With<Player>>,
+    mut ev_app_exit: EventWriter<AppExit>,
+) {
+    tick_limit.ticks += 1;
+    if tick_limit.ticks < tick_limit.limit {
+        return;
+    }
+    let ticks = tick_limit.ticks;
+    let seconds = ticks as f32 * fixed_time.period.as_secs_f32();
+    println!("simulated {ticks} ticks ({seconds:.1}s)");
+    let mut run = run_stats.finished.clone();
+    if !run_stats.added {
+        run.add(&stats);
+    }
+    let mut report = run.players;
+    report.sort_by_key(|player| player.id);
+    for player in &report {
+        let (health, controller, alive) = players.iter().find(|(id, ..)| id.0 == player.id).map_or(
+            (0, "", false),
+            |(_, health, controller, alive)| {
+                let controller = controller.map_or("", |controller| controller.name.as_str());
+                (health.current_health, controller, alive.is_some())
+            },
+        );
+        println!(
+            "{:>3} {:<8} {:<24} shots {:>5}  hits {:>4}  kills {:>3}  deaths {:>3}  health {:>4}  {}",
+            player.id,
+            player.name,
+            controller,
+            player.shots,
+            player.hits,
+            player.kills,
+            player.deaths,
+            health,
+            if alive { "alive" } else { "dead" }
+        );
+    }
+    ev_app_exit.send(AppExit);
+}
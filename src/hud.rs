@@ -0,0 +1,152 @@
+//! Each player's name, health and shooting cooldown, drawn in the world above them and hidden
+//! while they are dead.
+
+use bevy::{prelude::*, sprite::Anchor};
+
+use crate::{
+    combat::Shooter,
+    lobby::{PlayerColor, PlayerName},
+    player::{Alive, Health, PlayerConfig, PlayerOverrides},
+};
+
+/// Space between the top of a player and their health bar.
+const HUD_GAP: f32 = 8.0;
+const BAR_WIDTH: f32 = 50.0;
+const HEALTH_BAR_HEIGHT: f32 = 6.0;
+const COOLDOWN_BAR_HEIGHT: f32 = 3.0;
+const BAR_BACKGROUND: Color = Color::rgba(0.0, 0.0, 0.0, 0.6);
+
+/// Requires a window.
+pub struct HudPlugin;
+
+impl Plugin for HudPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, (spawn_player_huds, update_player_huds).chain());
+    }
+}
+
+/// Everything drawn above `player`, following them around.
+#[derive(Component)]
+pub struct PlayerHud {
+    pub player: Entity,
+    pub name_tag: Entity,
+    pub health_bar: Entity,
+    pub cooldown_bar: Entity,
+}
+
+pub fn spawn_player_huds(mut commands: Commands, players: Query<Entity, Added<PlayerName>>) {
+    for player in &players {
+        let name_tag = commands
+            .spawn(Text2dBundle {
+                text: Text::from_section(
+                    "",
+                    TextStyle {
+                        font_size: 20.0,
+                        ..default()
+                    },
+                )
+                .with_alignment(TextAlignment::Center),
+                text_anchor: Anchor::BottomCenter,
+                transform: Transform::from_xyz(0.0, HEALTH_BAR_HEIGHT, 0.0),
+                ..default()
+            })
+            .id();
+        let bar = |color, height, y, z| SpriteBundle {
+            sprite: Sprite {
+                color,
+                custom_size: Some(Vec2::new(BAR_WIDTH, height)),
+                anchor: Anchor::CenterLeft,
+                ..default()
+            },
+            transform: Transform::from_xyz(-BAR_WIDTH / 2.0, y, z),
+            ..default()
+        };
+        let cooldown_y = -(HEALTH_BAR_HEIGHT + COOLDOWN_BAR_HEIGHT) / 2.0;
+        // The fills sit on top of backgrounds showing what is missing.
+        let health_background = commands
+            .spawn(bar(BAR_BACKGROUND, HEALTH_BAR_HEIGHT, 0.0, 0.0))
+            .id();
+        let cooldown_background = commands
+            .spawn(bar(BAR_BACKGROUND, COOLDOWN_BAR_HEIGHT, cooldown_y, 0.0))
+            .id();
+        let health_bar = commands
+            .spawn(bar(Color::GREEN, HEALTH_BAR_HEIGHT, 0.0, 0.1))
+            .id();
+        let cooldown_bar = commands
+            .spawn(bar(Color::WHITE, COOLDOWN_BAR_HEIGHT, cooldown_y, 0.1))
+            .id();
+        commands
+            .spawn((
+                SpatialBundle::default(),
+                PlayerHud {
+                    player,
+                    name_tag,
+                    health_bar,
+                    cooldown_bar,
+                },
+                Name::new("Player HUD"),
+            ))
+            .push_children(&[
+                health_background,
+                cooldown_background,
+                health_bar,
+                cooldown_bar,
+                name_tag,
+            ]);
+    }
+}
+
+pub fn update_player_huds(
+    mut commands: Commands,
+    mut huds: Query<(Entity, &PlayerHud, &mut Transform, &mut Visibility)>,
+    players: Query<
+        (
+            &Transform,
+            &PlayerName,
+            &PlayerColor,
+            &Health,
+            &Shooter,
+            Option<&PlayerOverrides>,
+            Option<&Alive>,
+        ),
+        Without<PlayerHud>,
+    >,
+    mut name_tags: Query<&mut Text>,
+    mut bars: Query<(&mut Transform, &mut Sprite), (Without<PlayerHud>, Without<PlayerName>)>,
+    player_config: Res<PlayerConfig>,
+) {
+    for (entity, hud, mut transform, mut visibility) in &mut huds {
+        let Ok((player_transform, name, color, health, shooter, overrides, alive)) =
+            players.get(hud.player)
+        else {
+            commands.entity(entity).despawn_recursive();
+            continue;
+        };
+        visibility.set_if_neq(match alive {
+            Some(_) => Visibility::Inherited,
+            None => Visibility::Hidden,
+        });
+        // Clear of the player's corners however they are turned, and above every player.
+        let above = player_transform.scale.y * 0.75 + HUD_GAP;
+        transform.translation =
+            player_transform.translation.truncate().extend(100.0) + Vec3::Y * above;
+
+        if let Ok(mut text) = name_tags.get_mut(hud.name_tag) {
+            let section = &mut text.sections[0];
+            if section.value != name.0 {
+                section.value = name.0.clone();
+            }
+            section.style.color = color.color();
+        }
+
+        let starting_health = player_config.with_overrides(overrides).starting_health;
+        let health = (health.current_health as f32 / starting_health as f32).clamp(0.0, 1.0);
+        if let Ok((mut transform, mut sprite)) = bars.get_mut(hud.health_bar) {
+            transform.scale.x = health;
+            sprite.color = Color::rgb(1.0 - health, health, 0.2);
+        }
+        if let Ok((mut transform, _)) = bars.get_mut(hud.cooldown_bar) {
+            transform.scale.x = shooter.timer.percent();
+        }
+    }
+}
@@ -0,0 +1,412 @@
+//! Everything that enters the simulation from outside during a tick: controller sticks and
+//! buttons, controllers connecting and disconnecting, settings changes and the arena size.
+//!
+//! Gameplay systems only read [`TickInputs`], never the devices directly, so a match can be
+//! recorded and fed back through the same systems by [`crate::replay`].
+
+use bevy::{
+    input::{
+        gamepad::{GamepadConnection, GamepadConnectionEvent, GamepadSettings},
+        InputSystem,
+    },
+    prelude::*,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    arena::Arena,
+    combat::BulletConfig,
+    net::{MAX_CONTROLLERS_PER_CLIENT, REMOTE_ID_OFFSET},
+    player::{PlayerConfig, PlayerConfigChanged, PlayerOverrides, PlayerSetting},
+    replay::is_playing_back,
+    spectator::Spectator,
+    GameSet,
+};
+
+/// Holds the tick's inputs and orders the [`GameSet`]s, since every tick starts from its inputs.
+pub struct InputPlugin;
+
+impl Plugin for InputPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<TickInputs>()
+            .init_resource::<PendingInputs>()
+            .configure_sets(
+                FixedUpdate,
+                (
+                    GameSet::ReadInputs,
+                    GameSet::ApplyInputs,
+                    GameSet::Players,
+                    GameSet::Combat,
+                    GameSet::Deaths,
+                )
+                    .chain(),
+            )
+            .add_systems(FixedUpdate, apply_tick_inputs.in_set(GameSet::ApplyInputs));
+    }
+}
+
+/// Reads inputs from connected controllers, unless a replay is playing. Controllers only play
+/// without a [`Spectator`]. Requires a window.
+pub struct GamepadInputPlugin;
+
+impl Plugin for GamepadInputPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<GamepadIds>()
+            .add_systems(Startup, setup_gamepads)
+            .add_systems(
+                PreUpdate,
+                collect_gamepad_events
+                    .after(InputSystem)
+                    .run_if(not(is_playing_back)),
+            )
+            .add_systems(
+                FixedUpdate,
+                read_live_inputs
+                    .in_set(GameSet::ReadInputs)
+                    .run_if(not(is_playing_back)),
+            );
+    }
+}
+
+#[derive(Clone, Default, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PlayerInput {
+    pub id: usize,
+    pub movement: Vec2,
+    pub aim: Vec2,
+    pub pressed: Vec<GamepadButtonType>,
+    pub held: Vec<GamepadButtonType>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Connection {
+    pub id: usize,
+    pub name: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ConfigChange {
+    pub player_config: PlayerConfig,
+    pub bullet_config: BulletConfig,
+    /// The player config values that differ from the previous change.
+    pub player_settings_changed: Vec<PlayerSetting>,
+}
+
+/// The inputs for the tick currently being simulated.
+#[derive(Resource, Clone, Default, Debug, PartialEq, Serialize, Deserialize)]
+pub struct TickInputs {
+    pub connected: Vec<Connection>,
+    pub disconnected: Vec<usize>,
+    pub players: Vec<PlayerInput>,
+    pub config: Option<ConfigChange>,
+    /// Players whose handicaps were edited, with their complete new overrides.
+    pub player_overrides: Vec<(usize, PlayerOverrides)>,
+    pub arena: Option<Vec2>,
+}
+
+impl TickInputs {
+    pub fn player(&self, id: usize) -> Option<&PlayerInput> {
+        self.players.iter().find(|input| input.id == id)
+    }
+}
+
+/// The ID each gamepad on this machine plays with: the lowest free one when it connects, kept
+/// for as long as it stays connected. Platforms number gamepads however they like, and may
+/// keep counting up as they reconnect, but only so many IDs are set aside for this machine.
+#[derive(Resource, Clone, Default, Debug)]
+pub struct GamepadIds {
+    /// The gamepad with each ID.
+    gamepads: Vec<Option<Gamepad>>,
+    warned: bool,
+}
+
+impl GamepadIds {
+    /// The ID the gamepad plays with, if it has one.
+    pub fn get(&self, gamepad: Gamepad) -> Option<usize> {
+        self.gamepads.iter().position(|slot| *slot == Some(gamepad))
+    }
+
+    /// Gives the gamepad an ID below `limit` if it hasn't one, and returns its ID, or `None` if
+    /// they are all taken.
+    pub fn assign(&mut self, gamepad: Gamepad, limit: usize) -> Option<usize> {
+        if let Some(id) = self.get(gamepad) {
+            return Some(id);
+        }
+        match self.gamepads.iter().position(Option::is_none) {
+            Some(id) => {
+                self.gamepads[id] = Some(gamepad);
+                Some(id)
+            }
+            None if self.gamepads.len() < limit => {
+                self.gamepads.push(Some(gamepad));
+                Some(self.gamepads.len() - 1)
+            }
+            None => {
+                if !self.warned {
+                    warn!("only {limit} controllers can play from here");
+                    self.warned = true;
+                }
+                None
+            }
+        }
+    }
+
+    /// Frees the gamepad's ID for the next gamepad to connect, returning it.
+    pub fn release(&mut self, gamepad: Gamepad) -> Option<usize> {
+        let id = self.get(gamepad)?;
+        self.gamepads[id] = None;
+        Some(id)
+    }
+
+    /// Frees the IDs of gamepads no longer connected and gives the newly connected ones IDs.
+    fn update(&mut self, gamepads: &Gamepads, limit: usize) {
+        for slot in &mut self.gamepads {
+            if slot.is_some_and(|gamepad| !gamepads.contains(gamepad)) {
+                *slot = None;
+            }
+        }
+        for gamepad in gamepads.iter() {
+            self.assign(gamepad, limit);
+        }
+    }
+
+    /// The gamepads with IDs, and their IDs.
+    fn iter(&self) -> impl Iterator<Item = (usize, Gamepad)> + '_ {
+        self.gamepads
+            .iter()
+            .enumerate()
+            .filter_map(|(id, gamepad)| gamepad.map(|gamepad| (id, gamepad)))
+    }
+}
+
+/// The controllers plugged into this machine and what they are doing, for another machine to
+/// simulate. Only held buttons are kept; presses are worked out from them where they are used.
+///
+/// The other machine only has [`MAX_CONTROLLERS_PER_CLIENT`] IDs for this one's controllers,
+/// so they are numbered by [`GamepadIds`].
+#[derive(Resource, Clone, Default, Debug)]
+pub struct LocalControllers {
+    pub controllers: Vec<Connection>,
+    pub players: Vec<PlayerInput>,
+    ids: GamepadIds,
+}
+
+/// Edge-triggered inputs collected every frame, waiting for the next tick to consume them.
+#[derive(Resource, Default)]
+pub struct PendingInputs {
+    connected: Vec<Connection>,
+    disconnected: Vec<usize>,
+    pressed: Vec<(usize, GamepadButtonType)>,
+    pub player_overrides: Vec<(usize, PlayerOverrides)>,
+}
+
+impl PendingInputs {
+    /// Forgets the buttons pressed since the last tick.
+    pub fn clear_presses(&mut self) {
+        self.pressed.clear();
+    }
+
+    /// The most recent overrides waiting to be applied to a player, if any.
+    pub fn player_overrides(&self, id: usize) -> Option<&PlayerOverrides> {
+        self.player_overrides
+            .iter()
+            .rev()
+            .find(|(player_id, _)| *player_id == id)
+            .map(|(_, overrides)| overrides)
+    }
+}
+
+pub fn setup_gamepads(mut settings: ResMut<GamepadSettings>) {
+    let dz = 0.1;
+    settings.default_axis_settings.set_deadzone_lowerbound(-dz);
+    settings.default_axis_settings.set_deadzone_upperbound(dz);
+}
+
+pub fn collect_gamepad_events(
+    mut pending: ResMut<PendingInputs>,
+    mut ids: ResMut<GamepadIds>,
+    mut connection_events: EventReader<GamepadConnectionEvent>,
+    buttons: Res<Input<GamepadButton>>,
+) {
+    for connection_event in connection_events.iter() {
+        let gamepad = connection_event.gamepad;
+        match &connection_event.connection {
+            GamepadConnection::Connected(info) => {
+                // Higher IDs belong to remote players.
+                if let Some(id) = ids.assign(gamepad, REMOTE_ID_OFFSET) {
+                    pending.connected.push(Connection {
+                        id,
+                        name: info.name.clone(),
+                    });
+                }
+            }
+            GamepadConnection::Disconnected => {
+                if let Some(id) = ids.release(gamepad) {
+                    pending.disconnected.push(id);
+                }
+            }
+        }
+    }
+    for button in buttons.get_just_pressed() {
+        if let Some(id) = ids.get(button.gamepad) {
+            pending.pressed.push((id, button.button_type));
+        }
+    }
+}
+
+/// Reads this machine's controllers into [`LocalControllers`].
+pub fn read_local_controllers(
+    mut local: ResMut<LocalControllers>,
+    gamepads: Res<Gamepads>,
+    axes: Res<Axis<GamepadAxis>>,
+    buttons: Res<Input<GamepadButton>>,
+) {
+    local.ids.update(&gamepads, MAX_CONTROLLERS_PER_CLIENT);
+    let playing: Vec<(usize, Gamepad)> = local.ids.iter().collect();
+
+    let stick = |gamepad, x, y| {
+        let x = axes.get(GamepadAxis::new(gamepad, x)).unwrap_or(0.0);
+        let y = axes.get(GamepadAxis::new(gamepad, y)).unwrap_or(0.0);
+        Vec2::new(x, y)
+    };
+    local.controllers = playing
+        .iter()
+        .map(|&(id, gamepad)| Connection {
+            id,
+            name: gamepads.name(gamepad).unwrap_or_default().to_string(),
+        })
+        .collect();
+    local.players = playing
+        .iter()
+        .map(|&(id, gamepad)| PlayerInput {
+            id,
+            movement: stick(
+                gamepad,
+                GamepadAxisType::LeftStickX,
+                GamepadAxisType::LeftStickY,
+            ),
+            aim: stick(
+                gamepad,
+                GamepadAxisType::RightStickX,
+                GamepadAxisType::RightStickY,
+            ),
+            pressed: vec![],
+            held: buttons
+                .get_pressed()
+                .filter(|button| button.gamepad == gamepad)
+                .map(|button| button.button_type)
+                .collect(),
+        })
+        .collect();
+}
+
+pub fn read_live_inputs(
+    mut tick_inputs: ResMut<TickInputs>,
+    mut pending: ResMut<PendingInputs>,
+    axes: Res<Axis<GamepadAxis>>,
+    buttons: Res<Input<GamepadButton>>,
+    ids: Res<GamepadIds>,
+    windows: Query<&Window>,
+    player_config: Res<PlayerConfig>,
+    bullet_config: Res<BulletConfig>,
+    mut last_configs: Local<Option<(PlayerConfig, BulletConfig)>>,
+    mut last_arena: Local<Option<Vec2>>,
+    spectator: Option<Res<Spectator>>,
+) {
+    let stick = |gamepad, x, y| {
+        let x = axes.get(GamepadAxis::new(gamepad, x)).unwrap_or(0.0);
+        let y = axes.get(GamepadAxis::new(gamepad, y)).unwrap_or(0.0);
+        Vec2::new(x, y)
+    };
+    let players: Vec<PlayerInput> = ids
+        .iter()
+        .map(|(id, gamepad)| PlayerInput {
+            id,
+            movement: stick(
+                gamepad,
+                GamepadAxisType::LeftStickX,
+                GamepadAxisType::LeftStickY,
+            ),
+            aim: stick(
+                gamepad,
+                GamepadAxisType::RightStickX,
+                GamepadAxisType::RightStickY,
+            ),
+            pressed: pending
+                .pressed
+                .iter()
+                .filter(|(pressed_id, _)| *pressed_id == id)
+                .map(|(_, button)| *button)
+                .collect(),
+            held: buttons
+                .get_pressed()
+                .filter(|button| button.gamepad == gamepad)
+                .map(|button| button.button_type)
+                .collect(),
+        })
+        .collect();
+
+    let configs = (player_config.clone(), bullet_config.clone());
+    let config = if last_configs.as_ref() != Some(&configs) {
+        // The first time, players are spawned with the config as it is, nothing has changed.
+        let player_settings_changed = last_configs
+            .as_ref()
+            .map_or(vec![], |(last, _)| PlayerSetting::changed(last, &configs.0));
+        *last_configs = Some(configs.clone());
+        Some(ConfigChange {
+            player_config: configs.0,
+            bullet_config: configs.1,
+            player_settings_changed,
+        })
+    } else {
+        None
+    };
+
+    let window = windows.single();
+    let arena = Vec2::new(window.width() / 2.0, window.height() / 2.0);
+    let arena = if *last_arena != Some(arena) {
+        *last_arena = Some(arena);
+        Some(arena)
+    } else {
+        None
+    };
+
+    let connected = std::mem::take(&mut pending.connected);
+    let disconnected = std::mem::take(&mut pending.disconnected);
+    // A spectator's controllers steer the camera, so they never join.
+    let (connected, disconnected, players) = match spectator {
+        Some(_) => (vec![], vec![], vec![]),
+        None => (connected, disconnected, players),
+    };
+    *tick_inputs = TickInputs {
+        connected,
+        disconnected,
+        players,
+        config,
+        player_overrides: std::mem::take(&mut pending.player_overrides),
+        arena,
+    };
+    pending.pressed.clear();
+}
+
+pub fn apply_tick_inputs(
+    tick_inputs: Res<TickInputs>,
+    mut player_config: ResMut<PlayerConfig>,
+    mut bullet_config: ResMut<BulletConfig>,
+    mut arena: ResMut<Arena>,
+    mut ev_player_config_changed: EventWriter<PlayerConfigChanged>,
+) {
+    if let Some(config) = &tick_inputs.config {
+        if *player_config != config.player_config {
+            *player_config = config.player_config.clone();
+        }
+        if *bullet_config != config.bullet_config {
+            *bullet_config = config.bullet_config.clone();
+        }
+        for setting in &config.player_settings_changed {
+            ev_player_config_changed.send(PlayerConfigChanged(*setting));
+        }
+    }
+    if let Some(half_size) = tick_inputs.arena {
+        arena.half_size = half_size;
+    }
+}
@@ -0,0 +1,124 @@
+//! The row of join slots along the bottom of the screen, showing who is in the match and how
+//! to join or leave.
+
+use bevy::prelude::*;
+
+use crate::{
+    bot::is_bot,
+    lobby::{NamePicker, PlayerName},
+    net::{NetClient, MAX_CLIENTS},
+    player::{Controller, Disconnected, Leaving, Player, Slot, ID, MAX_PLAYERS},
+};
+
+const EMPTY_SLOT_COLOR: Color = Color::GRAY;
+
+/// Requires a window.
+pub struct JoinUiPlugin;
+
+impl Plugin for JoinUiPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Startup, setup_join_slots)
+            .add_systems(Update, update_join_slots);
+    }
+}
+
+/// The text of the join slot with this index.
+#[derive(Component)]
+pub struct JoinSlotText(pub usize);
+
+pub fn setup_join_slots(mut commands: Commands) {
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    bottom: Val::Px(10.0),
+                    width: Val::Percent(100.0),
+                    justify_content: JustifyContent::SpaceEvenly,
+                    ..default()
+                },
+                ..default()
+            },
+            Name::new("Join slots"),
+        ))
+        .with_children(|parent| {
+            for slot in 0..MAX_PLAYERS {
+                parent.spawn((
+                    TextBundle::from_section(
+                        "",
+                        TextStyle {
+                            font_size: 18.0,
+                            color: EMPTY_SLOT_COLOR,
+                            ..default()
+                        },
+                    )
+                    .with_text_alignment(TextAlignment::Center),
+                    JoinSlotText(slot),
+                ));
+            }
+        });
+}
+
+pub fn update_join_slots(
+    mut slot_texts: Query<(&JoinSlotText, &mut Text)>,
+    players: Query<(
+        &Slot,
+        &ID,
+        Option<&Controller>,
+        &Player,
+        &PlayerName,
+        Option<&NamePicker>,
+        Option<&Disconnected>,
+        Option<&Leaving>,
+    )>,
+    materials: Res<Assets<ColorMaterial>>,
+    client: Option<Res<NetClient>>,
+) {
+    let host_full = client.is_some_and(|client| client.is_host_full());
+    for (slot_text, mut text) in &mut slot_texts {
+        let section = &mut text.sections[0];
+        let Some((_, id, controller, player, name, picker, disconnected, leaving)) =
+            players.iter().find(|(slot, ..)| slot.0 == slot_text.0)
+        else {
+            section.value = match host_full {
+                true => format!(
+                    "{}\nHost is full ({MAX_CLIENTS} machines), waiting for room",
+                    slot_text.0 + 1
+                ),
+                false => format!("{}\nPress Start to join", slot_text.0 + 1),
+            };
+            section.style.color = EMPTY_SLOT_COLOR;
+            continue;
+        };
+        let name = match picker {
+            // Brackets around the character being edited.
+            Some(picker) => name
+                .chars()
+                .enumerate()
+                .map(|(i, c)| match i == picker.cursor {
+                    true => format!("[{c}]"),
+                    false => c.to_string(),
+                })
+                .collect(),
+            None => name.0.clone(),
+        };
+        let status = if let Some(disconnected) = disconnected {
+            format!(
+                "Reconnecting... {:.0}s",
+                disconnected.timer.remaining_secs()
+            )
+        } else if let Some(leaving) = leaving {
+            format!("Leaving in {:.1}s", leaving.timer.remaining_secs())
+        } else if let Some(controller) = controller.filter(|_| is_bot(id.0)) {
+            controller.name.clone()
+        } else if picker.is_some() {
+            "D-pad: name  LB/RB: color  Y: done".to_string()
+        } else {
+            "Y: name & color  Hold Select: leave".to_string()
+        };
+        section.value = format!("{}  {name}\n{status}", slot_text.0 + 1);
+        section.style.color = materials
+            .get(&player.material_handle)
+            .map_or(Color::WHITE, |material| material.color);
+    }
+}
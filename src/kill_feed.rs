@@ -0,0 +1,324 @@
+//! The kill feed in the top corner, and announcements in the middle of the screen for matches
+//! starting and ending, multi-kills and kill streaks.
+
+use bevy::prelude::*;
+
+use crate::{
+    combat::PlayerDied,
+    lobby::{PlayerColor, PlayerName},
+    player::{Player, ID},
+    stats::{MatchStats, MATCH_PLAYERS},
+};
+
+const KILL_FEED_LENGTH: usize = 5;
+const KILL_FEED_SECONDS: f32 = 6.0;
+const ANNOUNCEMENT_SECONDS: f32 = 2.5;
+/// Kills closer together than this count towards the same multi-kill.
+const MULTI_KILL_SECONDS: f32 = 3.0;
+/// Kills without dying it takes to be announced, and what is announced.
+const STREAKS: [(u32, &str); 3] = [
+    (3, "is on a killing spree"),
+    (5, "is on a rampage"),
+    (10, "is unstoppable"),
+];
+
+/// Requires a window.
+pub struct KillFeedPlugin;
+
+impl Plugin for KillFeedPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<Announcement>()
+            .init_resource::<KillTracker>()
+            .init_resource::<KillFeedCorrections>()
+            .add_systems(Startup, setup_kill_feed)
+            .add_systems(
+                Update,
+                (
+                    track_kills,
+                    announce_match_start_and_end,
+                    show_announcements,
+                    expire_kill_feed_entries,
+                )
+                    .chain(),
+            );
+    }
+}
+
+/// Text shown large in the middle of the screen for a moment.
+#[derive(Event)]
+pub struct Announcement(pub String);
+
+#[derive(Component)]
+pub struct KillFeed;
+
+#[derive(Component)]
+pub struct KillFeedEntry {
+    pub death: PlayerDied,
+    pub timer: Timer,
+}
+
+/// Deaths shown that turned out not to happen, and deaths that happened without being shown,
+/// as found by rolling back. They can't be sent as [`PlayerDied`], since the simulation has
+/// already played them out and would count them again.
+#[derive(Resource, Default)]
+pub struct KillFeedCorrections {
+    pub retracted: Vec<PlayerDied>,
+    pub missed: Vec<PlayerDied>,
+}
+
+impl KillFeedCorrections {
+    /// Corrects the feed from the deaths it was shown to those that really happened.
+    pub fn correct(&mut self, shown: &[PlayerDied], happened: &[PlayerDied]) {
+        let mut happened = happened.to_vec();
+        for death in shown {
+            match happened.iter().position(|other| other == death) {
+                Some(index) => {
+                    happened.remove(index);
+                }
+                None => self.retracted.push(*death),
+            }
+        }
+        self.missed.extend(happened);
+    }
+}
+
+#[derive(Component)]
+pub struct AnnouncementText {
+    pub timer: Timer,
+}
+
+#[derive(Default)]
+struct KillRecord {
+    id: usize,
+    streak: u32,
+    multi_kill: u32,
+    last_kill_seconds: f32,
+}
+
+/// Everyone's streaks and multi-kills since the match started.
+#[derive(Resource, Default)]
+pub struct KillTracker {
+    players: Vec<KillRecord>,
+    match_on: bool,
+}
+
+impl KillTracker {
+    fn player(&mut self, id: usize) -> &mut KillRecord {
+        let index = match self.players.iter().position(|player| player.id == id) {
+            Some(index) => index,
+            None => {
+                self.players.push(KillRecord { id, ..default() });
+                self.players.len() - 1
+            }
+        };
+        &mut self.players[index]
+    }
+}
+
+pub fn setup_kill_feed(mut commands: Commands) {
+    commands.spawn((
+        NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                top: Val::Px(10.0),
+                right: Val::Px(10.0),
+                flex_direction: FlexDirection::Column,
+                align_items: AlignItems::FlexEnd,
+                ..default()
+            },
+            ..default()
+        },
+        KillFeed,
+        Name::new("Kill feed"),
+    ));
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    top: Val::Percent(25.0),
+                    width: Val::Percent(100.0),
+                    justify_content: JustifyContent::Center,
+                    ..default()
+                },
+                ..default()
+            },
+            Name::new("Announcements"),
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                TextBundle::from_section(
+                    "",
+                    TextStyle {
+                        font_size: 48.0,
+                        ..default()
+                    },
+                ),
+                AnnouncementText {
+                    timer: Timer::from_seconds(ANNOUNCEMENT_SECONDS, TimerMode::Once),
+                },
+            ));
+        });
+}
+
+/// Adds deaths to the kill feed and announces multi-kills and streaks.
+pub fn track_kills(
+    mut commands: Commands,
+    mut ev_player_died: EventReader<PlayerDied>,
+    mut ev_announcement: EventWriter<Announcement>,
+    mut tracker: ResMut<KillTracker>,
+    mut corrections: ResMut<KillFeedCorrections>,
+    players: Query<(&ID, &PlayerName, &PlayerColor)>,
+    kill_feed: Query<(Entity, Option<&Children>), With<KillFeed>>,
+    feed_entries: Query<&KillFeedEntry>,
+    time: Res<Time>,
+) {
+    let Ok((kill_feed, entries)) = kill_feed.get_single() else {
+        return;
+    };
+    let mut entries: Vec<Entity> = entries.map_or(vec![], |entries| entries.to_vec());
+    for death in std::mem::take(&mut corrections.retracted) {
+        let shown = entries.iter().position(|entry| {
+            feed_entries
+                .get(*entry)
+                .is_ok_and(|entry| entry.death == death)
+        });
+        if let Some(index) = shown {
+            commands.entity(entries.remove(index)).despawn_recursive();
+        }
+        if let Some(killer) = death.killer {
+            let record = tracker.player(killer);
+            record.streak = record.streak.saturating_sub(1);
+        }
+    }
+    let name = |id: usize| {
+        players
+            .iter()
+            .find(|(player_id, _, _)| player_id.0 == id)
+            .map_or(("?".to_string(), Color::WHITE), |(_, name, color)| {
+                (name.0.clone(), color.color())
+            })
+    };
+    let section = |(value, color): (String, Color)| {
+        TextSection::new(
+            value,
+            TextStyle {
+                font_size: 22.0,
+                color,
+                ..default()
+            },
+        )
+    };
+    let missed = std::mem::take(&mut corrections.missed);
+    for ev in ev_player_died.iter().chain(&missed) {
+        tracker.player(ev.victim).streak = 0;
+        let sections = match ev.killer {
+            Some(killer) => vec![
+                section(name(killer)),
+                section((" > ".to_string(), Color::WHITE)),
+                section(name(ev.victim)),
+            ],
+            None => vec![
+                section(name(ev.victim)),
+                section((" self-destructed".to_string(), Color::WHITE)),
+            ],
+        };
+        let entry = commands
+            .spawn((
+                TextBundle::from_sections(sections),
+                KillFeedEntry {
+                    death: *ev,
+                    timer: Timer::from_seconds(KILL_FEED_SECONDS, TimerMode::Once),
+                },
+            ))
+            .id();
+        commands.entity(kill_feed).add_child(entry);
+        entries.push(entry);
+        while entries.len() > KILL_FEED_LENGTH {
+            commands.entity(entries.remove(0)).despawn_recursive();
+        }
+
+        let Some(killer) = ev.killer else {
+            continue;
+        };
+        let now = time.elapsed_seconds();
+        let record = tracker.player(killer);
+        record.streak += 1;
+        record.multi_kill = if now - record.last_kill_seconds <= MULTI_KILL_SECONDS {
+            record.multi_kill + 1
+        } else {
+            1
+        };
+        record.last_kill_seconds = now;
+        let (streak, multi_kill) = (record.streak, record.multi_kill);
+        let killer_name = name(killer).0;
+        let multi_kill = match multi_kill {
+            0 | 1 => None,
+            2 => Some("Double kill!"),
+            3 => Some("Triple kill!"),
+            _ => Some("Multi kill!"),
+        };
+        if let Some(text) = multi_kill {
+            ev_announcement.send(Announcement(format!("{killer_name}: {text}")));
+        }
+        if let Some((_, text)) = STREAKS.iter().find(|(kills, _)| *kills == streak) {
+            ev_announcement.send(Announcement(format!("{killer_name} {text}")));
+        }
+    }
+}
+
+/// A match is on while there are enough players to fight each other. The winner is the one
+/// the match's stats have, so that a tie is announced as one.
+pub fn announce_match_start_and_end(
+    mut tracker: ResMut<KillTracker>,
+    mut ev_announcement: EventWriter<Announcement>,
+    players: Query<(), With<Player>>,
+    stats: Res<MatchStats>,
+) {
+    let match_on = players.iter().count() >= MATCH_PLAYERS;
+    if match_on == tracker.match_on {
+        return;
+    }
+    if match_on {
+        *tracker = KillTracker::default();
+        ev_announcement.send(Announcement("Fight!".into()));
+    } else {
+        ev_announcement.send(Announcement(match stats.winner() {
+            Some(winner) => format!("Match over, {} wins!", winner.name),
+            None => "Match over".into(),
+        }));
+    }
+    tracker.match_on = match_on;
+}
+
+pub fn show_announcements(
+    mut ev_announcement: EventReader<Announcement>,
+    mut texts: Query<(&mut Text, &mut AnnouncementText)>,
+    time: Res<Time>,
+) {
+    for (mut text, mut announcement) in &mut texts {
+        // The latest announcement replaces any still showing.
+        if let Some(Announcement(value)) = ev_announcement.iter().last() {
+            text.sections[0].value = value.clone();
+            announcement.timer.reset();
+        }
+        announcement.timer.tick(time.delta());
+        let remaining = 1.0 - announcement.timer.percent();
+        // Fade out over the last half second.
+        let alpha = (remaining * ANNOUNCEMENT_SECONDS / 0.5).min(1.0);
+        text.sections[0].style.color.set_a(alpha);
+    }
+}
+
+pub fn expire_kill_feed_entries(
+    mut commands: Commands,
+    mut entries: Query<(Entity, &mut KillFeedEntry)>,
+    time: Res<Time>,
+) {
+    for (entity, mut entry) in &mut entries {
+        entry.timer.tick(time.delta());
+        if entry.timer.finished() {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
@@ -0,0 +1,66 @@
+// Bevy systems routinely take many parameters and nested query types.
+#![allow(clippy::too_many_arguments, clippy::type_complexity)]
+
+pub mod arena;
+pub mod balance;
+pub mod behavior;
+pub mod bot;
+pub mod cli;
+pub mod combat;
+pub mod config_file;
+pub mod config_ui;
+pub mod headless;
+pub mod hud;
+pub mod input;
+pub mod join_ui;
+pub mod kill_feed;
+pub mod lobby;
+pub mod net;
+pub mod pause_menu;
+pub mod player;
+pub mod replay;
+pub mod rollback;
+pub mod settings;
+pub mod spectator;
+pub mod stats;
+
+use bevy::{app::PluginGroupBuilder, prelude::*};
+use rand::rngs::StdRng;
+
+/// The stages of a simulation tick in [`FixedUpdate`], run in this order. Embedders and tests
+/// can add their own systems to these sets or order against them.
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub enum GameSet {
+    /// Fills [`input::TickInputs`] for the tick, from controllers, a replay or bots.
+    ReadInputs,
+    /// Applies settings and arena changes carried by the tick's inputs.
+    ApplyInputs,
+    /// Players joining and leaving, moving, aiming and pressing buttons.
+    Players,
+    /// Firing, moving bullets and resolving hits.
+    Combat,
+    /// Players whose health ran out this tick die.
+    Deaths,
+}
+
+/// The whole simulation, independent of windowing, rendering and where inputs come from, so
+/// the same game runs in a window, headless and in tests. The app must insert
+/// [`player::PlayerConfig`], [`combat::BulletConfig`] and [`GameRng`], and feed
+/// [`input::TickInputs`] from a system in [`GameSet::ReadInputs`].
+pub struct GamePlugins;
+
+impl PluginGroup for GamePlugins {
+    fn build(self) -> PluginGroupBuilder {
+        PluginGroupBuilder::start::<Self>()
+            .add(input::InputPlugin)
+            .add(arena::ArenaPlugin)
+            .add(player::PlayerPlugin)
+            .add(lobby::LobbyPlugin)
+            .add(combat::CombatPlugin)
+            .add(stats::StatsPlugin)
+    }
+}
+
+/// The only source of randomness in the simulation, seeded so that replays are deterministic.
+#[derive(Resource, Deref, DerefMut)]
+pub struct GameRng(pub StdRng);
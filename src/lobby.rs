@@ -0,0 +1,177 @@
+//! Each player's name and color. A player opens the picker with North and, while it is open,
+//! spells a name with the D-pad and steps through a palette of colors with the bumpers. No
+//! two players share a color.
+
+use bevy::prelude::*;
+
+use crate::{
+    input::TickInputs,
+    player::{Player, ID},
+    GameSet,
+};
+
+/// Colors that are easy to tell apart, from each other and from the background.
+pub const PALETTE: [Color; 10] = [
+    Color::rgb(0.90, 0.15, 0.15),
+    Color::rgb(0.15, 0.45, 0.95),
+    Color::rgb(0.15, 0.80, 0.25),
+    Color::rgb(0.98, 0.85, 0.10),
+    Color::rgb(0.95, 0.50, 0.05),
+    Color::rgb(0.60, 0.25, 0.90),
+    Color::rgb(0.10, 0.85, 0.85),
+    Color::rgb(0.95, 0.40, 0.75),
+    Color::rgb(0.60, 0.35, 0.15),
+    Color::rgb(0.95, 0.95, 0.95),
+];
+
+/// The characters a name can be spelled with, in the order Up steps through them.
+pub const NAME_CHARACTERS: &str = "ABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789 ";
+
+pub const MAX_NAME_LENGTH: usize = 8;
+
+pub struct LobbyPlugin;
+
+impl Plugin for LobbyPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<PlayerName>()
+            .register_type::<PlayerColor>()
+            .register_type::<NamePicker>()
+            .add_systems(FixedUpdate, pick_name_and_color.in_set(GameSet::Players));
+    }
+}
+
+/// The name shown above a player and in the join slots.
+#[derive(Component, Default, Reflect, Clone, Deref)]
+#[reflect(Component)]
+pub struct PlayerName(pub String);
+
+impl PlayerName {
+    /// The name of a player who hasn't picked one, e.g. "P1" in the first join slot.
+    pub fn for_slot(slot: usize) -> Self {
+        PlayerName(format!("P{}", slot + 1))
+    }
+}
+
+/// The player's color, as an index into [`PALETTE`].
+#[derive(Component, Default, Reflect, Clone, Copy, PartialEq, Debug)]
+#[reflect(Component)]
+pub struct PlayerColor(pub usize);
+
+impl PlayerColor {
+    pub fn color(&self) -> Color {
+        PALETTE[self.0]
+    }
+
+    /// The first color after `self` in the direction `step` that nobody in `taken` has, or
+    /// `self` if every other color is taken.
+    pub fn next_free(self, step: isize, taken: &[PlayerColor]) -> PlayerColor {
+        (1..PALETTE.len())
+            .map(|offset| {
+                let index = self.0 as isize + step * offset as isize;
+                PlayerColor(index.rem_euclid(PALETTE.len() as isize) as usize)
+            })
+            .find(|color| !taken.contains(color))
+            .unwrap_or(self)
+    }
+
+    /// The first color in the palette that nobody in `taken` has.
+    pub fn first_free(taken: &[PlayerColor]) -> PlayerColor {
+        (0..PALETTE.len())
+            .map(PlayerColor)
+            .find(|color| !taken.contains(color))
+            .unwrap_or_default()
+    }
+}
+
+/// A player with the picker open, editing the character under `cursor`.
+#[derive(Component, Default, Reflect, Clone)]
+#[reflect(Component)]
+pub struct NamePicker {
+    pub cursor: usize,
+}
+
+pub fn pick_name_and_color(
+    tick_inputs: Res<TickInputs>,
+    mut players: Query<(
+        Entity,
+        &ID,
+        &Player,
+        &mut PlayerName,
+        &mut PlayerColor,
+        Option<&mut NamePicker>,
+    )>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut commands: Commands,
+) {
+    let mut taken: Vec<PlayerColor> = players.iter().map(|(.., color, _)| *color).collect();
+    // In ID order, since players changing color on the same tick take turns.
+    let mut in_order: Vec<(usize, Entity)> = players
+        .iter()
+        .map(|(entity, id, ..)| (id.0, entity))
+        .collect();
+    in_order.sort();
+    for (_, entity) in in_order {
+        let (entity, id, player, mut name, mut color, mut picker) =
+            players.get_mut(entity).unwrap();
+        let Some(input) = tick_inputs.player(id.0) else {
+            continue;
+        };
+        for button in &input.pressed {
+            if *button == GamepadButtonType::North {
+                if picker.is_some() {
+                    let trimmed = name.trim().to_string();
+                    if !trimmed.is_empty() {
+                        name.0 = trimmed;
+                    }
+                    commands.entity(entity).remove::<NamePicker>();
+                } else {
+                    commands.entity(entity).insert(NamePicker::default());
+                }
+                continue;
+            }
+            let Some(picker) = picker.as_deref_mut() else {
+                continue;
+            };
+            let mut characters: Vec<char> = name.chars().collect();
+            match button {
+                GamepadButtonType::DPadLeft => picker.cursor = picker.cursor.saturating_sub(1),
+                GamepadButtonType::DPadRight if picker.cursor + 1 < MAX_NAME_LENGTH => {
+                    picker.cursor += 1;
+                    if picker.cursor >= characters.len() {
+                        characters.push('A');
+                    }
+                }
+                GamepadButtonType::DPadUp | GamepadButtonType::DPadDown => {
+                    let step = if *button == GamepadButtonType::DPadUp {
+                        1
+                    } else {
+                        -1
+                    };
+                    let alphabet: Vec<char> = NAME_CHARACTERS.chars().collect();
+                    let Some(character) = characters.get_mut(picker.cursor) else {
+                        continue;
+                    };
+                    let index = alphabet.iter().position(|c| c == character).unwrap_or(0);
+                    let index = (index as isize + step).rem_euclid(alphabet.len() as isize);
+                    *character = alphabet[index as usize];
+                }
+                GamepadButtonType::LeftTrigger | GamepadButtonType::RightTrigger => {
+                    let step = if *button == GamepadButtonType::RightTrigger {
+                        1
+                    } else {
+                        -1
+                    };
+                    let next = color.next_free(step, &taken);
+                    taken.retain(|other| *other != *color);
+                    taken.push(next);
+                    *color = next;
+                    if let Some(material) = materials.get_mut(&player.material_handle) {
+                        material.color = color.color();
+                    }
+                }
+                _ => (),
+            }
+            name.0 = characters.into_iter().collect();
+        }
+    }
+}
@@ -0,0 +1,888 @@
+//! Multiplayer over a local network. The host runs the match as usual. Clients send the host
+//! what their controllers are doing every tick. The host feeds that into [`TickInputs`] as
+//! more controllers, so remote players join, play and leave like anyone else. In return the
+//! host sends every client a [`Snapshot`] of the match each tick, and clients show it.
+//!
+//! Spectators get the snapshots too but send no controllers, so they watch without joining and
+//! without taking up a client's block of IDs.
+//!
+//! Everything goes over UDP, one message per datagram. A lost message is made up for by the
+//! next one, since each carries the whole state rather than what changed.
+
+use std::{
+    io,
+    net::{SocketAddr, ToSocketAddrs, UdpSocket},
+    time::Duration,
+};
+
+use bevy::{app::AppExit, prelude::*, sprite::MaterialMesh2dBundle};
+use bincode::Options;
+use rand::{rngs::StdRng, SeedableRng};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::{
+    arena::{Arena, Map},
+    combat::{Bullet, BulletConfig, BulletMesh, PlayerDied, Shooter, SpawnProtection},
+    headless::clear_tick_inputs,
+    input::{
+        read_live_inputs, read_local_controllers, setup_gamepads, Connection, LocalControllers,
+        PlayerInput, TickInputs,
+    },
+    lobby::{PlayerColor, PlayerName},
+    player::{
+        Alive, Controller, DeathMarker, Health, Player, PlayerConfig, PlayerMesh, PlayerOverrides,
+        Slot, ID,
+    },
+    GameRng, GameSet,
+};
+
+/// Bumped whenever the messages change, so that mismatched builds ignore each other.
+const PROTOCOL_VERSION: u32 = 4;
+/// The largest UDP payload.
+pub const MAX_DATAGRAM: usize = 65507;
+/// Remote controllers get IDs from here up, clear of local controllers and below bots.
+pub const REMOTE_ID_OFFSET: usize = 16;
+pub const MAX_CLIENTS: usize = 6;
+/// How many controllers each client can have, and the IDs each client's players take up.
+pub const MAX_CONTROLLERS_PER_CLIENT: usize = 8;
+/// More spectators than this are turned away, since each costs a snapshot every tick.
+pub const MAX_SPECTATORS: usize = 16;
+/// How long without hearing from the other side before giving up on it.
+const TIMEOUT_SECONDS: f32 = 3.0;
+/// How long deaths are repeated in snapshots, so a client missing a few still sees them.
+const DEATH_RESEND_SECONDS: f32 = 1.0;
+
+/// Takes remote players into the match. Requires the [`NetHost`] resource.
+pub struct NetHostPlugin;
+
+impl Plugin for NetHostPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            FixedUpdate,
+            (
+                read_client_inputs
+                    .in_set(GameSet::ReadInputs)
+                    .after(read_live_inputs)
+                    .after(clear_tick_inputs),
+                send_snapshots.after(GameSet::Deaths),
+            ),
+        );
+    }
+}
+
+/// Shows a match hosted elsewhere and, unless spectating, sends the host this machine's
+/// controllers. Requires the [`NetClient`] resource. The simulation's systems still run, but
+/// with no inputs of their own they only play out what the snapshots show, such as deaths.
+pub struct NetClientPlugin;
+
+impl Plugin for NetClientPlugin {
+    fn build(&self, app: &mut App) {
+        // Nothing random happens on a client, but the simulation expects the resource.
+        app.insert_resource(GameRng(StdRng::seed_from_u64(0)))
+            .init_resource::<LocalControllers>()
+            .add_systems(
+                Startup,
+                setup_gamepads.run_if(resource_exists::<Gamepads>()),
+            )
+            .add_systems(
+                FixedUpdate,
+                (
+                    read_local_controllers.run_if(resource_exists::<Gamepads>()),
+                    exchange_with_host.after(read_local_controllers),
+                )
+                    .in_set(GameSet::ReadInputs),
+            )
+            .add_systems(Last, leave_host_on_exit);
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+enum ClientMessage {
+    /// The client's controllers and, for each, the sticks and the buttons held down.
+    Inputs {
+        controllers: Vec<Connection>,
+        players: Vec<PlayerInput>,
+    },
+    /// The client is closing, so its players are disconnected straight away.
+    Leave,
+    /// The client only watches, and wants snapshots without joining.
+    Spectate,
+}
+
+#[derive(Serialize, Deserialize)]
+enum HostMessage {
+    /// The state of the match, sent every tick.
+    Snapshot(Snapshot),
+    /// In answer to a client asking to join, when [`MAX_CLIENTS`] are in already. The client
+    /// keeps asking, and joins once one leaves.
+    Full,
+}
+
+/// The whole visible state of the match after a tick.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub tick: u32,
+    pub arena: Vec2,
+    pub map: Map,
+    pub player_config: PlayerConfig,
+    pub bullet_config: BulletConfig,
+    pub players: Vec<PlayerState>,
+    pub bullets: Vec<BulletState>,
+    /// The deaths of the last [`DEATH_RESEND_SECONDS`].
+    pub deaths: Vec<Death>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PlayerState {
+    pub id: usize,
+    pub slot: usize,
+    pub name: String,
+    pub controller: String,
+    pub color: usize,
+    pub position: Vec2,
+    pub rotation: f32,
+    pub scale: f32,
+    pub health: i32,
+    pub alive: bool,
+    /// Seconds of spawn protection so far, if the player is protected.
+    pub protection: Option<f32>,
+    /// How far through the shooting delay the player is, from 0 to 1.
+    pub cooldown: f32,
+    pub overrides: Option<PlayerOverrides>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct BulletState {
+    /// Whose bullet it is.
+    pub id: usize,
+    pub position: Vec2,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Death {
+    pub tick: u32,
+    pub victim: usize,
+    pub killer: Option<usize>,
+}
+
+pub fn encode<T: Serialize>(message: &T) -> Vec<u8> {
+    bincode::DefaultOptions::new()
+        .serialize(&(PROTOCOL_VERSION, message))
+        .expect("messages always serialize")
+}
+
+fn encoded_size<T: Serialize>(value: &T) -> usize {
+    bincode::DefaultOptions::new()
+        .serialized_size(value)
+        .expect("messages always serialize") as usize
+}
+
+/// How many bullets fit in a datagram along with the rest of a snapshot, which has none yet.
+/// The rest are left out.
+fn snapshot_bullet_room(snapshot: &Snapshot) -> usize {
+    // Every message starts with the version and which message it is, the same size for each.
+    let rest = encoded_size(&(PROTOCOL_VERSION, HostMessage::Full)) + encoded_size(snapshot);
+    // Numbers take fewer bytes the smaller they are, so these are the most a bullet and the
+    // bullet count can take.
+    let bullet = encoded_size(&BulletState {
+        id: usize::MAX,
+        position: Vec2::ZERO,
+    });
+    let count = encoded_size(&usize::MAX);
+    MAX_DATAGRAM.saturating_sub(rest + count) / bullet
+}
+
+/// The message in a datagram, or `None` if it isn't one from the same version of the game.
+pub fn decode<T: DeserializeOwned>(datagram: &[u8]) -> Option<T> {
+    let (version, message): (u32, T) = bincode::DefaultOptions::new().deserialize(datagram).ok()?;
+    (version == PROTOCOL_VERSION).then_some(message)
+}
+
+/// Replaces sticks that aren't numbers, so a bad message can't break the simulation.
+pub fn sanitize(stick: Vec2) -> Vec2 {
+    if stick.is_finite() {
+        stick.clamp_length_max(1.0)
+    } else {
+        Vec2::ZERO
+    }
+}
+
+/// The ID in the match of the controller with `id` on the remote machine numbered `index`.
+pub fn remote_id(index: usize, id: usize) -> usize {
+    REMOTE_ID_OFFSET + index * MAX_CONTROLLERS_PER_CLIENT + id
+}
+
+/// A machine playing in the hosted match.
+struct RemoteClient {
+    address: SocketAddr,
+    /// Which block of remote IDs the client's players have.
+    index: usize,
+    /// The client's controllers, by their IDs on the client.
+    controllers: Vec<Connection>,
+    /// The latest inputs, by the controllers' IDs on the client.
+    players: Vec<PlayerInput>,
+    /// Buttons pressed since the last tick, by the controllers' IDs on the client.
+    pressed: Vec<(usize, GamepadButtonType)>,
+    silent_ticks: u32,
+}
+
+impl RemoteClient {
+    fn remote_id(&self, id: usize) -> usize {
+        remote_id(self.index, id)
+    }
+
+    /// The controller name used in the match, telling apart the same controller on two
+    /// machines.
+    fn controller_name(&self, connection: &Connection) -> String {
+        format!("{} ({})", connection.name, self.address.ip())
+    }
+}
+
+/// A machine watching the hosted match.
+struct RemoteSpectator {
+    address: SocketAddr,
+    silent_ticks: u32,
+}
+
+#[derive(Resource)]
+pub struct NetHost {
+    socket: UdpSocket,
+    clients: Vec<RemoteClient>,
+    spectators: Vec<RemoteSpectator>,
+    recent_deaths: Vec<Death>,
+    tick: u32,
+}
+
+impl NetHost {
+    pub fn bind(address: impl ToSocketAddrs) -> io::Result<Self> {
+        let socket = UdpSocket::bind(address)?;
+        socket.set_nonblocking(true)?;
+        Ok(NetHost {
+            socket,
+            clients: vec![],
+            spectators: vec![],
+            recent_deaths: vec![],
+            tick: 0,
+        })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.socket.local_addr()
+    }
+
+    /// The addresses of the clients in the match.
+    pub fn clients(&self) -> Vec<SocketAddr> {
+        self.clients.iter().map(|client| client.address).collect()
+    }
+
+    /// The addresses of the spectators watching the match.
+    pub fn spectators(&self) -> Vec<SocketAddr> {
+        self.spectators
+            .iter()
+            .map(|spectator| spectator.address)
+            .collect()
+    }
+
+    /// Keeps sending snapshots to a spectator, taking them in if there's room.
+    fn hear_from_spectator(&mut self, address: SocketAddr) {
+        let full = self.spectators.len() >= MAX_SPECTATORS;
+        match self
+            .spectators
+            .iter_mut()
+            .find(|spectator| spectator.address == address)
+        {
+            Some(spectator) => spectator.silent_ticks = 0,
+            None if !full => {
+                info!("{address} is spectating");
+                self.spectators.push(RemoteSpectator {
+                    address,
+                    silent_ticks: 0,
+                });
+            }
+            None => {}
+        }
+    }
+
+    fn disconnect(client: RemoteClient, tick_inputs: &mut TickInputs) {
+        info!("{} left", client.address);
+        for connection in &client.controllers {
+            tick_inputs
+                .disconnected
+                .push(client.remote_id(connection.id));
+        }
+    }
+}
+
+/// Adds the inputs of remote players to the tick's, with their IDs made unique in the match.
+pub fn read_client_inputs(
+    mut host: ResMut<NetHost>,
+    mut tick_inputs: ResMut<TickInputs>,
+    fixed_time: Res<FixedTime>,
+) {
+    let host = &mut *host;
+    let mut buffer = [0; MAX_DATAGRAM];
+    loop {
+        let (length, address) = match host.socket.recv_from(&mut buffer) {
+            Ok(received) => received,
+            Err(err) if err.kind() == io::ErrorKind::WouldBlock => break,
+            // A client that went away can make the next receive fail, which is no reason to
+            // stop hearing from the others.
+            Err(_) => continue,
+        };
+        let Some(message) = decode::<ClientMessage>(&buffer[..length]) else {
+            continue;
+        };
+        let known = host
+            .clients
+            .iter()
+            .position(|client| client.address == address);
+        let (controllers, players) = match (message, known) {
+            (
+                ClientMessage::Inputs {
+                    controllers,
+                    players,
+                },
+                _,
+            ) => (controllers, players),
+            (ClientMessage::Leave, Some(index)) => {
+                NetHost::disconnect(host.clients.remove(index), &mut tick_inputs);
+                continue;
+            }
+            (ClientMessage::Leave, None) => {
+                host.spectators
+                    .retain(|spectator| spectator.address != address);
+                continue;
+            }
+            (ClientMessage::Spectate, _) => {
+                host.hear_from_spectator(address);
+                continue;
+            }
+        };
+        let client = match known {
+            Some(index) => &mut host.clients[index],
+            None => {
+                let Some(index) = (0..MAX_CLIENTS)
+                    .find(|index| host.clients.iter().all(|client| client.index != *index))
+                else {
+                    let _ = host.socket.send_to(&encode(&HostMessage::Full), address);
+                    continue;
+                };
+                info!("{address} joined");
+                host.clients.push(RemoteClient {
+                    address,
+                    index,
+                    controllers: vec![],
+                    players: vec![],
+                    pressed: vec![],
+                    silent_ticks: 0,
+                });
+                host.clients.last_mut().unwrap()
+            }
+        };
+        client.silent_ticks = 0;
+
+        let controllers: Vec<Connection> = controllers
+            .into_iter()
+            .filter(|connection| connection.id < MAX_CONTROLLERS_PER_CLIENT)
+            .collect();
+        for connection in &controllers {
+            if !client.controllers.contains(connection) {
+                tick_inputs.connected.push(Connection {
+                    id: client.remote_id(connection.id),
+                    name: client.controller_name(connection),
+                });
+            }
+        }
+        for connection in &client.controllers {
+            if controllers.iter().all(|other| other.id != connection.id) {
+                tick_inputs
+                    .disconnected
+                    .push(client.remote_id(connection.id));
+            }
+        }
+        client.controllers = controllers;
+
+        // Presses are worked out from what is held, so that a lost message loses none.
+        for input in &players {
+            let held_before = client
+                .players
+                .iter()
+                .find(|before| before.id == input.id)
+                .map_or(&[][..], |before| &before.held[..]);
+            for button in &input.held {
+                if !held_before.contains(button) {
+                    client.pressed.push((input.id, *button));
+                }
+            }
+        }
+        client.players = players;
+    }
+
+    let timeout_ticks = (TIMEOUT_SECONDS / fixed_time.period.as_secs_f32()) as u32;
+    for client in &mut host.clients {
+        client.silent_ticks += 1;
+    }
+    for spectator in &mut host.spectators {
+        spectator.silent_ticks += 1;
+    }
+    host.spectators
+        .retain(|spectator| spectator.silent_ticks <= timeout_ticks);
+    while let Some(index) = host
+        .clients
+        .iter()
+        .position(|client| client.silent_ticks > timeout_ticks)
+    {
+        NetHost::disconnect(host.clients.remove(index), &mut tick_inputs);
+    }
+
+    for client in &mut host.clients {
+        for input in &client.players {
+            if client
+                .controllers
+                .iter()
+                .all(|connection| connection.id != input.id)
+            {
+                continue;
+            }
+            tick_inputs.players.push(PlayerInput {
+                id: client.remote_id(input.id),
+                movement: sanitize(input.movement),
+                aim: sanitize(input.aim),
+                pressed: client
+                    .pressed
+                    .iter()
+                    .filter(|(id, _)| *id == input.id)
+                    .map(|(_, button)| *button)
+                    .collect(),
+                held: input.held.clone(),
+            });
+        }
+        client.pressed.clear();
+    }
+}
+
+pub fn send_snapshots(
+    mut host: ResMut<NetHost>,
+    players: Query<
+        (
+            &ID,
+            &Slot,
+            &PlayerName,
+            &PlayerColor,
+            &Transform,
+            &Health,
+            Option<&Controller>,
+            Option<&Alive>,
+            Option<&SpawnProtection>,
+            &Shooter,
+            Option<&PlayerOverrides>,
+        ),
+        With<Player>,
+    >,
+    bullets: Query<(&ID, &Transform), With<Bullet>>,
+    mut ev_player_died: EventReader<PlayerDied>,
+    arena: Res<Arena>,
+    map: Res<Map>,
+    player_config: Res<PlayerConfig>,
+    bullet_config: Res<BulletConfig>,
+    fixed_time: Res<FixedTime>,
+) {
+    host.tick += 1;
+    let tick = host.tick;
+    for ev in ev_player_died.iter() {
+        host.recent_deaths.push(Death {
+            tick,
+            victim: ev.victim,
+            killer: ev.killer,
+        });
+    }
+    let resend_ticks = (DEATH_RESEND_SECONDS / fixed_time.period.as_secs_f32()) as u32;
+    host.recent_deaths
+        .retain(|death| death.tick + resend_ticks > tick);
+    if host.clients.is_empty() && host.spectators.is_empty() {
+        return;
+    }
+
+    let mut snapshot = Snapshot {
+        tick,
+        arena: arena.half_size,
+        map: *map,
+        player_config: player_config.clone(),
+        bullet_config: bullet_config.clone(),
+        players: players
+            .iter()
+            .map(
+                |(
+                    id,
+                    slot,
+                    name,
+                    color,
+                    transform,
+                    health,
+                    controller,
+                    alive,
+                    protection,
+                    shooter,
+                    overrides,
+                )| {
+                    PlayerState {
+                        id: id.0,
+                        slot: slot.0,
+                        name: name.0.clone(),
+                        controller: controller.map_or_else(String::new, |c| c.name.clone()),
+                        color: color.0,
+                        position: transform.translation.truncate(),
+                        rotation: transform.rotation.to_euler(EulerRot::XYZ).2,
+                        scale: transform.scale.x,
+                        health: health.current_health,
+                        alive: alive.is_some(),
+                        protection: protection.map(|protection| protection.timer.elapsed_secs()),
+                        cooldown: shooter.timer.percent(),
+                        overrides: overrides.cloned(),
+                    }
+                },
+            )
+            .collect(),
+        bullets: vec![],
+        deaths: host.recent_deaths.clone(),
+    };
+    snapshot.bullets = bullets
+        .iter()
+        .take(snapshot_bullet_room(&snapshot))
+        .map(|(id, transform)| BulletState {
+            id: id.0,
+            position: transform.translation.truncate(),
+        })
+        .collect();
+    let datagram = encode(&HostMessage::Snapshot(snapshot));
+    let addresses = host.clients.iter().map(|client| client.address);
+    let addresses = addresses.chain(host.spectators.iter().map(|spectator| spectator.address));
+    for address in addresses {
+        if let Err(err) = host.socket.send_to(&datagram, address) {
+            warn!("failed to send a snapshot to {address}: {err}");
+        }
+    }
+}
+
+#[derive(Resource)]
+pub struct NetClient {
+    socket: UdpSocket,
+    /// Whether this machine only watches, sending the host none of its controllers.
+    spectating: bool,
+    /// The tick of the latest snapshot shown.
+    tick: Option<u32>,
+    silent_ticks: u32,
+    /// Whether the host last answered that it has no room for this machine.
+    full: bool,
+}
+
+impl NetClient {
+    pub fn connect(host: impl ToSocketAddrs) -> io::Result<Self> {
+        Self::open(host, false)
+    }
+
+    /// Like [`NetClient::connect`], but to watch the match rather than play in it.
+    pub fn spectate(host: impl ToSocketAddrs) -> io::Result<Self> {
+        Self::open(host, true)
+    }
+
+    fn open(host: impl ToSocketAddrs, spectating: bool) -> io::Result<Self> {
+        let host = host
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no address for the host"))?;
+        let local: SocketAddr = match host {
+            SocketAddr::V4(_) => ([0, 0, 0, 0], 0).into(),
+            SocketAddr::V6(_) => ([0; 8], 0).into(),
+        };
+        let socket = UdpSocket::bind(local)?;
+        socket.connect(host)?;
+        socket.set_nonblocking(true)?;
+        Ok(NetClient {
+            socket,
+            spectating,
+            tick: None,
+            silent_ticks: 0,
+            full: false,
+        })
+    }
+
+    /// Whether snapshots are coming in from the host.
+    pub fn is_connected(&self, fixed_time: &FixedTime) -> bool {
+        self.tick.is_some()
+            && (self.silent_ticks as f32 * fixed_time.period.as_secs_f32()) < TIMEOUT_SECONDS
+    }
+
+    /// Whether the host turned this machine away for having [`MAX_CLIENTS`] already.
+    pub fn is_host_full(&self) -> bool {
+        self.full
+    }
+}
+
+/// Sends the host this tick's inputs and shows the latest snapshot from it.
+pub fn exchange_with_host(
+    mut client: ResMut<NetClient>,
+    local: Res<LocalControllers>,
+    mut commands: Commands,
+    mut players: Query<(
+        Entity,
+        &ID,
+        &Player,
+        &mut Transform,
+        &mut Health,
+        &mut PlayerName,
+        &mut PlayerColor,
+        Option<&Alive>,
+    )>,
+    mut bullets: Query<
+        (Entity, &mut ID, &mut Transform, &mut Handle<ColorMaterial>),
+        (With<Bullet>, Without<Player>),
+    >,
+    death_markers: Query<(Entity, &DeathMarker)>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    player_mesh: Res<PlayerMesh>,
+    bullet_mesh: Res<BulletMesh>,
+    mut arena: ResMut<Arena>,
+    mut map: ResMut<Map>,
+    mut player_config: ResMut<PlayerConfig>,
+    mut bullet_config: ResMut<BulletConfig>,
+    mut ev_player_died: EventWriter<PlayerDied>,
+) {
+    let message = match client.spectating {
+        true => ClientMessage::Spectate,
+        false => ClientMessage::Inputs {
+            controllers: local.controllers.clone(),
+            players: local.players.clone(),
+        },
+    };
+    // The host may not be up yet, in which case the next tick tries again.
+    let _ = client.socket.send(&encode(&message));
+
+    let mut latest: Option<Snapshot> = None;
+    let mut buffer = [0; MAX_DATAGRAM];
+    loop {
+        let length = match client.socket.recv(&mut buffer) {
+            Ok(length) => length,
+            Err(err) if err.kind() == io::ErrorKind::WouldBlock => break,
+            Err(_) => continue,
+        };
+        let snapshot = match decode::<HostMessage>(&buffer[..length]) {
+            Some(HostMessage::Snapshot(snapshot)) => snapshot,
+            Some(HostMessage::Full) => {
+                client.full = true;
+                continue;
+            }
+            None => continue,
+        };
+        // Datagrams can arrive out of order.
+        let newest = latest
+            .as_ref()
+            .map_or(client.tick, |latest| Some(latest.tick));
+        if newest.map_or(true, |tick| snapshot.tick > tick) {
+            latest = Some(snapshot);
+        }
+    }
+    let Some(snapshot) = latest else {
+        client.silent_ticks += 1;
+        return;
+    };
+    // The first snapshot shows the match as it is, without the deaths leading up to it.
+    let last_tick = client.tick.unwrap_or(snapshot.tick);
+    client.tick = Some(snapshot.tick);
+    client.silent_ticks = 0;
+    client.full = false;
+
+    arena.half_size = snapshot.arena;
+    if *map != snapshot.map {
+        *map = snapshot.map;
+    }
+    if *player_config != snapshot.player_config {
+        *player_config = snapshot.player_config.clone();
+    }
+    if *bullet_config != snapshot.bullet_config {
+        *bullet_config = snapshot.bullet_config.clone();
+    }
+
+    // Deaths are played out by the simulation, leaving markers and showing in the kill feed.
+    let new_deaths: Vec<&Death> = snapshot
+        .deaths
+        .iter()
+        .filter(|death| death.tick > last_tick)
+        .collect();
+    for death in &new_deaths {
+        ev_player_died.send(PlayerDied {
+            victim: death.victim,
+            killer: death.killer,
+        });
+    }
+
+    for (entity, id, ..) in &players {
+        if snapshot.players.iter().all(|state| state.id != id.0) {
+            commands.entity(entity).despawn();
+            for (marker, death_marker) in &death_markers {
+                if death_marker.id == id.0 {
+                    commands.entity(marker).despawn_recursive();
+                }
+            }
+        }
+    }
+    for state in &snapshot.players {
+        let transform = Transform::from_translation(state.position.extend(state.id as f32))
+            .with_rotation(Quat::from_rotation_z(state.rotation))
+            .with_scale(Vec3::new(state.scale, state.scale, 0.0));
+        let existing = players.iter_mut().find(|(_, id, ..)| id.0 == state.id);
+        let entity = match existing {
+            Some((
+                entity,
+                _,
+                player,
+                mut player_transform,
+                mut health,
+                mut name,
+                mut color,
+                alive,
+            )) => {
+                *player_transform = transform;
+                health.current_health = state.health;
+                if name.0 != state.name {
+                    name.0 = state.name.clone();
+                }
+                if color.0 != state.color {
+                    *color = PlayerColor(state.color);
+                    if let Some(material) = materials.get_mut(&player.material_handle) {
+                        material.color = color.color();
+                    }
+                }
+                let dying = new_deaths.iter().any(|death| death.victim == state.id);
+                match (state.alive, alive.is_some()) {
+                    (true, false) => {
+                        commands
+                            .entity(entity)
+                            .insert((Alive, Visibility::Inherited));
+                        for (marker, death_marker) in &death_markers {
+                            if death_marker.id == state.id {
+                                commands.entity(marker).despawn_recursive();
+                            }
+                        }
+                    }
+                    // Players who died this tick are left to the simulation to kill.
+                    (false, true) if !dying => {
+                        commands
+                            .entity(entity)
+                            .remove::<Alive>()
+                            .insert(Visibility::Hidden);
+                    }
+                    _ => (),
+                }
+                entity
+            }
+            None => {
+                let color = PlayerColor(state.color);
+                let material_handle = materials.add(ColorMaterial::from(color.color()));
+                let mut player_commands = commands.spawn((
+                    MaterialMesh2dBundle {
+                        mesh: player_mesh.mesh_handle.clone().into(),
+                        material: material_handle.clone(),
+                        transform,
+                        visibility: match state.alive {
+                            true => Visibility::Inherited,
+                            false => Visibility::Hidden,
+                        },
+                        ..default()
+                    },
+                    Player { material_handle },
+                    ID(state.id),
+                    Slot(state.slot),
+                    PlayerName(state.name.clone()),
+                    color,
+                    Health {
+                        current_health: state.health,
+                    },
+                    Name::new(format!("Player: {}", state.controller)),
+                    Controller {
+                        name: state.controller.clone(),
+                    },
+                ));
+                if state.alive {
+                    player_commands.insert(Alive);
+                }
+                player_commands.id()
+            }
+        };
+        match state.protection {
+            // Long enough not to run out before the next snapshot, and flashing in time with
+            // the host.
+            Some(elapsed) => {
+                let mut protection = SpawnProtection::new(elapsed + TIMEOUT_SECONDS);
+                protection
+                    .timer
+                    .set_elapsed(Duration::from_secs_f32(elapsed));
+                commands.entity(entity).insert(protection);
+            }
+            None => {
+                commands.entity(entity).remove::<SpawnProtection>();
+            }
+        }
+        // For the cooldown bar. Paused, so that the client never fires.
+        let mut timer = Timer::from_seconds(1.0, TimerMode::Repeating);
+        timer.set_elapsed(Duration::from_secs_f32(state.cooldown));
+        timer.pause();
+        commands.entity(entity).insert(Shooter { timer });
+        match &state.overrides {
+            Some(overrides) => commands.entity(entity).insert(overrides.clone()),
+            None => commands.entity(entity).remove::<PlayerOverrides>(),
+        };
+    }
+
+    let player_materials: Vec<(usize, Handle<ColorMaterial>)> = players
+        .iter()
+        .map(|(_, id, player, ..)| (id.0, player.material_handle.clone()))
+        .collect();
+    let material_for = |id: usize| {
+        player_materials
+            .iter()
+            .find(|(player_id, _)| *player_id == id)
+            .map(|(_, material)| material.clone())
+            .unwrap_or_default()
+    };
+    let bullet_scale = Vec3::new(
+        snapshot.bullet_config.scale,
+        snapshot.bullet_config.scale,
+        0.0,
+    );
+    let mut states = snapshot.bullets.iter();
+    // Bullets aren't told apart, so existing entities are reused for whichever bullets come.
+    for (entity, mut id, mut transform, mut material) in &mut bullets {
+        let Some(state) = states.next() else {
+            commands.entity(entity).despawn();
+            continue;
+        };
+        if id.0 != state.id {
+            id.0 = state.id;
+            *material = material_for(state.id);
+        }
+        *transform = Transform::from_translation(state.position.extend(state.id as f32))
+            .with_scale(bullet_scale);
+    }
+    for state in states {
+        commands.spawn((
+            MaterialMesh2dBundle {
+                mesh: bullet_mesh.mesh_handle.clone().into(),
+                material: material_for(state.id),
+                transform: Transform::from_translation(state.position.extend(state.id as f32))
+                    .with_scale(bullet_scale),
+                ..default()
+            },
+            Bullet,
+            ID(state.id),
+            Name::new("Bullet"),
+        ));
+    }
+}
+
+/// Tells the host straight away, rather than leaving it to time out.
+fn leave_host_on_exit(client: Res<NetClient>, mut ev_app_exit: EventReader<AppExit>) {
+    if ev_app_exit.iter().next().is_some() {
+        let _ = client.socket.send(&encode(&ClientMessage::Leave));
+    }
+}
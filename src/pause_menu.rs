@@ -0,0 +1,228 @@
+//! The pause menu, opened by any player pressing Start. It stops the clock, so no ticks run
+//! while it is open, and lets the settings be changed with a controller: Up and Down pick a
+//! setting, Left and Right change it, South toggles, and Start or East resumes.
+
+use bevy::prelude::*;
+
+use crate::{
+    combat::BulletConfig,
+    config_ui::{read_setting, setting_mut, SettingValue, SETTING_COUNT},
+    input::{GamepadIds, PendingInputs},
+    player::{Disconnected, Player, PlayerConfig, ID},
+    replay::is_playing_back,
+};
+
+const SELECTED_COLOR: Color = Color::YELLOW;
+
+/// Requires a window.
+pub struct PauseMenuPlugin;
+
+impl Plugin for PauseMenuPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<PauseMenu>()
+            .init_resource::<GamepadIds>()
+            .add_systems(Startup, setup_pause_menu)
+            .add_systems(
+                Update,
+                (navigate_pause_menu, draw_pause_menu)
+                    .chain()
+                    .run_if(not(is_playing_back)),
+            );
+    }
+}
+
+#[derive(Resource, Default)]
+pub struct PauseMenu {
+    pub open: bool,
+    /// The index of the highlighted setting, or [`SETTING_COUNT`] for Resume.
+    pub selected: usize,
+}
+
+impl SettingValue<'_> {
+    /// Steps the value `direction` steps up or down, or toggles it. Returns whether it changed.
+    fn adjust(&mut self, direction: i32) -> bool {
+        match self {
+            SettingValue::Float { value, range, step } => {
+                let new = (**value + *step * direction as f32).clamp(*range.start(), *range.end());
+                std::mem::replace(*value, new) != new
+            }
+            SettingValue::Int { value, range, step } => {
+                let new = (**value + *step * direction).clamp(*range.start(), *range.end());
+                std::mem::replace(*value, new) != new
+            }
+            SettingValue::Bool(value) => {
+                **value = !**value;
+                true
+            }
+        }
+    }
+
+    fn display(&self) -> String {
+        match self {
+            SettingValue::Float { value, step, .. } if step.fract() != 0.0 => format!("{value:.2}"),
+            SettingValue::Float { value, .. } => format!("{value:.0}"),
+            SettingValue::Int { value, .. } => value.to_string(),
+            SettingValue::Bool(value) => if **value { "on" } else { "off" }.to_string(),
+        }
+    }
+}
+
+#[derive(Component)]
+pub struct PauseMenuText;
+
+pub fn setup_pause_menu(mut commands: Commands) {
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    width: Val::Percent(100.0),
+                    height: Val::Percent(100.0),
+                    justify_content: JustifyContent::Center,
+                    align_items: AlignItems::Center,
+                    ..default()
+                },
+                background_color: Color::rgba(0.0, 0.0, 0.0, 0.7).into(),
+                visibility: Visibility::Hidden,
+                ..default()
+            },
+            PauseMenuText,
+            Name::new("Pause menu"),
+        ))
+        .with_children(|parent| {
+            parent.spawn(TextBundle::default());
+        });
+}
+
+pub fn navigate_pause_menu(
+    mut pause_menu: ResMut<PauseMenu>,
+    buttons: Res<Input<GamepadButton>>,
+    gamepad_ids: Res<GamepadIds>,
+    players: Query<&ID, (With<Player>, Without<Disconnected>)>,
+    mut player_config: ResMut<PlayerConfig>,
+    mut bullet_config: ResMut<BulletConfig>,
+    mut pending_inputs: ResMut<PendingInputs>,
+    mut time: ResMut<Time>,
+) {
+    let pressed = |button_type| {
+        buttons
+            .get_just_pressed()
+            .any(|button| button.button_type == button_type)
+    };
+    if !pause_menu.open {
+        let player_pressed_start = buttons.get_just_pressed().any(|button| {
+            button.button_type == GamepadButtonType::Start
+                && players
+                    .iter()
+                    .any(|id| gamepad_ids.get(button.gamepad) == Some(id.0))
+        });
+        if player_pressed_start {
+            pause_menu.open = true;
+            pause_menu.selected = 0;
+            time.pause();
+        }
+        return;
+    }
+
+    let resume_selected = pause_menu.selected == SETTING_COUNT;
+    if pressed(GamepadButtonType::Start)
+        || pressed(GamepadButtonType::East)
+        || resume_selected && pressed(GamepadButtonType::South)
+    {
+        pause_menu.open = false;
+        // Buttons pressed to work the menu are not meant for the game.
+        pending_inputs.clear_presses();
+        time.unpause();
+        return;
+    }
+    if pressed(GamepadButtonType::DPadUp) {
+        pause_menu.selected = pause_menu.selected.checked_sub(1).unwrap_or(SETTING_COUNT);
+    }
+    if pressed(GamepadButtonType::DPadDown) {
+        pause_menu.selected = (pause_menu.selected + 1) % (SETTING_COUNT + 1);
+    }
+    if resume_selected {
+        return;
+    }
+    // Bypassed so that only an actual change, below, counts as one.
+    let Some(mut setting) = setting_mut(
+        pause_menu.selected,
+        player_config.bypass_change_detection(),
+        bullet_config.bypass_change_detection(),
+    ) else {
+        return;
+    };
+    let mut changed = false;
+    for (button, direction) in [
+        (GamepadButtonType::DPadLeft, -1),
+        (GamepadButtonType::DPadRight, 1),
+    ] {
+        if pressed(button) {
+            changed |= setting.value.adjust(direction);
+        }
+    }
+    if pressed(GamepadButtonType::South) && matches!(setting.value, SettingValue::Bool(_)) {
+        changed |= setting.value.adjust(1);
+    }
+    if changed {
+        player_config.set_changed();
+        bullet_config.set_changed();
+    }
+}
+
+pub fn draw_pause_menu(
+    pause_menu: Res<PauseMenu>,
+    mut menus: Query<(&mut Visibility, &Children), With<PauseMenuText>>,
+    mut texts: Query<&mut Text>,
+    player_config: Res<PlayerConfig>,
+    bullet_config: Res<BulletConfig>,
+) {
+    for (mut visibility, children) in &mut menus {
+        visibility.set_if_neq(match pause_menu.open {
+            true => Visibility::Inherited,
+            false => Visibility::Hidden,
+        });
+        if !pause_menu.open {
+            continue;
+        }
+        let Ok(mut text) = texts.get_mut(children[0]) else {
+            continue;
+        };
+        let line = |value: String, selected: bool| {
+            TextSection::new(
+                value,
+                TextStyle {
+                    font_size: 28.0,
+                    color: if selected {
+                        SELECTED_COLOR
+                    } else {
+                        Color::WHITE
+                    },
+                    ..default()
+                },
+            )
+        };
+        let mut sections = vec![line("Paused\n\n".into(), false)];
+        for index in 0..SETTING_COUNT {
+            let selected = pause_menu.selected == index;
+            let arrows = if selected { ("< ", " >") } else { ("", "") };
+            let Some(value) = read_setting(index, &player_config, &bullet_config, |setting| {
+                format!(
+                    "{:>20}  {}{}{}\n",
+                    setting.label,
+                    arrows.0,
+                    setting.value.display(),
+                    arrows.1
+                )
+            }) else {
+                break;
+            };
+            sections.push(line(value, selected));
+        }
+        sections.push(line(
+            "\nResume".into(),
+            pause_menu.selected == SETTING_COUNT,
+        ));
+        text.sections = sections;
+    }
+}
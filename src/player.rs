@@ -0,0 +1,767 @@
+//! Players: joining and leaving, movement and aiming, the Mode button, and applying
+//! [`PlayerConfig`] changes.
+
+use std::{f32::consts::PI, time::Duration};
+
+use bevy::{prelude::*, sprite::MaterialMesh2dBundle, utils::HashSet};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    arena::{Arena, SpawnSelector},
+    combat::{Collider, PlayerDied, Shooter, SpawnProtection},
+    input::{apply_tick_inputs, Connection, TickInputs},
+    lobby::{PlayerColor, PlayerName},
+    GameRng, GameSet,
+};
+
+/// The most players that can be in a match at once.
+pub const MAX_PLAYERS: usize = 8;
+
+/// How long a player holds Select to leave the match.
+pub const LEAVE_HOLD_SECONDS: f32 = 1.0;
+
+pub struct PlayerPlugin;
+
+impl Plugin for PlayerPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<PlayerConfig>()
+            .init_resource::<PlayerMesh>()
+            .register_type::<PlayerMesh>()
+            .register_type::<Player>()
+            .register_type::<Alive>()
+            .register_type::<ID>()
+            .register_type::<Health>()
+            .init_resource::<Controllers>()
+            .register_type::<Slot>()
+            .register_type::<Leaving>()
+            .register_type::<Controller>()
+            .register_type::<Disconnected>()
+            .register_type::<DeathMarker>()
+            .register_type::<PlayerOverrides>()
+            .add_event::<PlayerConfigChanged>()
+            .add_systems(Startup, setup_player_mesh)
+            .add_systems(
+                FixedUpdate,
+                apply_player_overrides
+                    .in_set(GameSet::ApplyInputs)
+                    .after(apply_tick_inputs),
+            )
+            .add_systems(
+                FixedUpdate,
+                (
+                    gamepad_connections,
+                    join_and_leave.after(gamepad_connections),
+                    player_movement,
+                    player_rotation,
+                    respond_to_player_config_change,
+                    handle_buttons,
+                )
+                    .in_set(GameSet::Players),
+            )
+            .add_systems(FixedUpdate, kill_player.in_set(GameSet::Deaths));
+    }
+}
+
+/// Sent once for every [`PlayerConfig`] value that changed, so that players are only updated
+/// for what did.
+#[derive(Event, Clone, Copy, PartialEq, Debug)]
+pub struct PlayerConfigChanged(pub PlayerSetting);
+
+/// One of the values in [`PlayerConfig`].
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum PlayerSetting {
+    Speed,
+    TurningSpeed,
+    ShootingDelay,
+    Scale,
+    Invincible,
+    StartingHealth,
+    MinSpawnDistance,
+    SpawnProtection,
+    ReconnectGrace,
+}
+
+impl PlayerSetting {
+    /// The values that differ between two configs.
+    pub fn changed(old: &PlayerConfig, new: &PlayerConfig) -> Vec<PlayerSetting> {
+        // Destructured so that a new field can't be forgotten here.
+        let PlayerConfig {
+            speed,
+            turning_speed,
+            shooting_delay,
+            scale,
+            invincible,
+            starting_health,
+            min_spawn_distance,
+            spawn_protection,
+            reconnect_grace,
+        } = old;
+        [
+            (*speed != new.speed, PlayerSetting::Speed),
+            (
+                *turning_speed != new.turning_speed,
+                PlayerSetting::TurningSpeed,
+            ),
+            (
+                *shooting_delay != new.shooting_delay,
+                PlayerSetting::ShootingDelay,
+            ),
+            (*scale != new.scale, PlayerSetting::Scale),
+            (*invincible != new.invincible, PlayerSetting::Invincible),
+            (
+                *starting_health != new.starting_health,
+                PlayerSetting::StartingHealth,
+            ),
+            (
+                *min_spawn_distance != new.min_spawn_distance,
+                PlayerSetting::MinSpawnDistance,
+            ),
+            (
+                *spawn_protection != new.spawn_protection,
+                PlayerSetting::SpawnProtection,
+            ),
+            (
+                *reconnect_grace != new.reconnect_grace,
+                PlayerSetting::ReconnectGrace,
+            ),
+        ]
+        .into_iter()
+        .filter_map(|(changed, setting)| changed.then_some(setting))
+        .collect()
+    }
+}
+
+pub fn setup_player_mesh(mut meshes: ResMut<Assets<Mesh>>, mut player_mesh: ResMut<PlayerMesh>) {
+    player_mesh.mesh_handle = meshes.add(shape::Box::new(1.0, 1.0, 1.0).into());
+}
+
+#[derive(Resource, Default, Reflect)]
+#[reflect(Resource)]
+pub struct PlayerMesh {
+    pub mesh_handle: Handle<Mesh>,
+}
+
+#[derive(Resource, Default, Reflect, Clone, PartialEq, Debug, Serialize, Deserialize)]
+#[reflect(Resource)]
+pub struct PlayerConfig {
+    pub speed: f32,
+    pub turning_speed: f32,
+    pub shooting_delay: f32,
+    pub scale: f32,
+    pub invincible: bool,
+    pub starting_health: i32,
+    /// Players spawn at least this far from living enemies whenever there is room.
+    pub min_spawn_distance: f32,
+    /// Seconds after spawning during which bullets pass through a player.
+    pub spawn_protection: f32,
+    /// Seconds a disconnected player waits for their controller before leaving the match.
+    pub reconnect_grace: f32,
+}
+
+impl PlayerConfig {
+    /// This config as it applies to a player with the given overrides.
+    pub fn with_overrides(&self, overrides: Option<&PlayerOverrides>) -> PlayerConfig {
+        let mut config = self.clone();
+        if let Some(overrides) = overrides {
+            config.speed = overrides.speed.unwrap_or(config.speed);
+            config.starting_health = overrides.starting_health.unwrap_or(config.starting_health);
+            config.shooting_delay = overrides.shooting_delay.unwrap_or(config.shooting_delay);
+            config.scale = overrides.scale.unwrap_or(config.scale);
+        }
+        config
+    }
+}
+
+/// Per-player handicaps. Each value that is set replaces the one in [`PlayerConfig`].
+#[derive(Component, Default, Reflect, Clone, PartialEq, Debug, Serialize, Deserialize)]
+#[reflect(Component)]
+pub struct PlayerOverrides {
+    pub speed: Option<f32>,
+    pub starting_health: Option<i32>,
+    pub shooting_delay: Option<f32>,
+    pub scale: Option<f32>,
+}
+
+#[derive(Component, Default, Reflect, Clone)]
+#[reflect(Component)]
+pub struct Player {
+    pub material_handle: Handle<ColorMaterial>,
+}
+
+#[derive(Component, Default, Reflect, Clone)]
+#[reflect(Component)]
+pub struct Alive;
+
+#[derive(Component, Default, Reflect, Clone, Deref, DerefMut)]
+#[reflect(Component)]
+pub struct ID(pub usize);
+
+#[derive(Component, Default, Reflect, Clone)]
+#[reflect(Component)]
+pub struct Health {
+    pub current_health: i32,
+}
+
+/// Connected controllers, whether or not they have joined the match.
+#[derive(Resource, Clone, Default)]
+pub struct Controllers(pub Vec<Connection>);
+
+/// Which of the [`MAX_PLAYERS`] join slots a player took.
+#[derive(Component, Default, Reflect, Clone)]
+#[reflect(Component)]
+pub struct Slot(pub usize);
+
+/// A player holding Select to leave the match.
+#[derive(Component, Default, Reflect, Clone)]
+#[reflect(Component)]
+pub struct Leaving {
+    pub timer: Timer,
+}
+
+/// The controller a player joined with, to recognise it when it reconnects.
+#[derive(Component, Default, Reflect, Clone)]
+#[reflect(Component)]
+pub struct Controller {
+    pub name: String,
+}
+
+/// A player whose controller disconnected, waiting for it to come back.
+#[derive(Component, Default, Reflect, Clone)]
+#[reflect(Component)]
+pub struct Disconnected {
+    /// Whether the player is brought back alive when the controller reconnects.
+    pub was_alive: bool,
+    /// Runs for [`PlayerConfig::reconnect_grace`], after which the player leaves the match.
+    pub timer: Timer,
+}
+
+/// Marks where the player with this ID died, until they respawn.
+#[derive(Component, Default, Reflect, Clone)]
+#[reflect(Component)]
+pub struct DeathMarker {
+    pub id: usize,
+}
+
+/// Keeps track of connected controllers. When a player's controller disconnects they are
+/// paused, hidden and out of collisions for [`PlayerConfig::reconnect_grace`] seconds, and a
+/// controller with the same name connecting takes them back over.
+pub fn gamepad_connections(
+    mut commands: Commands,
+    tick_inputs: Res<TickInputs>,
+    fixed_time: Res<FixedTime>,
+    mut controllers: ResMut<Controllers>,
+    mut players: Query<
+        (
+            Entity,
+            &mut ID,
+            &mut Transform,
+            Option<&Alive>,
+            Option<&mut Disconnected>,
+            Option<&Controller>,
+        ),
+        With<Player>,
+    >,
+    mut death_markers: Query<(Entity, &mut DeathMarker)>,
+    player_config: Res<PlayerConfig>,
+) {
+    let mut reconnected = vec![];
+    for connection in &tick_inputs.connected {
+        controllers
+            .0
+            .retain(|controller| controller.id != connection.id);
+        controllers.0.push(connection.clone());
+        // Freed IDs are handed out again, so a different controller can get a waiting player's.
+        // Only the same kind of controller takes a player back, and the ID only picks between
+        // players who had that kind.
+        let waiting = |same_id: bool| {
+            players
+                .iter()
+                .find_map(|(entity, id, _, _, disconnected, controller)| {
+                    let matches = controller.is_some_and(|c| c.name == connection.name)
+                        && (!same_id || id.0 == connection.id);
+                    (disconnected.is_some() && matches && !reconnected.contains(&entity))
+                        .then_some(entity)
+                })
+        };
+        if let Some(entity) = waiting(true).or_else(|| waiting(false)) {
+            reconnected.push(entity);
+            let (_, id, ..) = players.get(entity).unwrap();
+            let old_id = id.0;
+            // Whoever else is waiting with the new ID swaps, so that IDs stay unique among players.
+            for (other, mut id, mut transform, ..) in &mut players {
+                if other != entity && id.0 == connection.id {
+                    id.0 = old_id;
+                    transform.translation.z = old_id as f32;
+                }
+            }
+            for (_, mut death_marker) in &mut death_markers {
+                if death_marker.id == old_id {
+                    death_marker.id = connection.id;
+                } else if death_marker.id == connection.id {
+                    death_marker.id = old_id;
+                }
+            }
+            let (_, mut id, mut transform, _, disconnected, _) = players.get_mut(entity).unwrap();
+            id.0 = connection.id;
+            transform.translation.z = connection.id as f32;
+            let mut player_commands = commands.entity(entity);
+            player_commands.remove::<Disconnected>();
+            if disconnected.unwrap().was_alive {
+                player_commands.insert((Alive, Visibility::Inherited));
+                if !player_config.invincible {
+                    player_commands.insert(Collider);
+                }
+                if player_config.spawn_protection > 0.0 {
+                    player_commands.insert(SpawnProtection::new(player_config.spawn_protection));
+                }
+            }
+        }
+    }
+    for disconnected_id in &tick_inputs.disconnected {
+        controllers
+            .0
+            .retain(|controller| controller.id != *disconnected_id);
+        for (player_entity, id, _, alive, disconnected, _) in &players {
+            if id.0 == *disconnected_id && disconnected.is_none() {
+                commands
+                    .entity(player_entity)
+                    .remove::<(Alive, Collider, SpawnProtection)>()
+                    .insert((
+                        Visibility::Hidden,
+                        Disconnected {
+                            was_alive: alive.is_some(),
+                            timer: Timer::from_seconds(
+                                player_config.reconnect_grace,
+                                TimerMode::Once,
+                            ),
+                        },
+                    ));
+            }
+        }
+    }
+    for (player_entity, id, _, _, disconnected, _) in &mut players {
+        let Some(mut disconnected) = disconnected else {
+            continue;
+        };
+        if reconnected.contains(&player_entity) {
+            continue;
+        }
+        disconnected.timer.tick(fixed_time.period);
+        if disconnected.timer.finished() {
+            commands.entity(player_entity).despawn();
+            for (entity, death_marker) in &death_markers {
+                if death_marker.id == id.0 {
+                    commands.entity(entity).despawn_recursive();
+                }
+            }
+        }
+    }
+}
+
+/// Controllers press Start to join the match in the first free [`Slot`], and players hold
+/// Select for [`LEAVE_HOLD_SECONDS`] to leave it.
+pub fn join_and_leave(
+    mut commands: Commands,
+    tick_inputs: Res<TickInputs>,
+    fixed_time: Res<FixedTime>,
+    controllers: Res<Controllers>,
+    mut players: Query<
+        (
+            Entity,
+            &ID,
+            &Slot,
+            &Transform,
+            Option<&Alive>,
+            Option<&mut Leaving>,
+            Option<&PlayerColor>,
+        ),
+        With<Player>,
+    >,
+    death_markers: Query<(Entity, &DeathMarker)>,
+    spawn_selector: SpawnSelector,
+    mut rng: ResMut<GameRng>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    player_mesh: Res<PlayerMesh>,
+    player_config: Res<PlayerConfig>,
+) {
+    let mut enemies: Vec<Vec2> = players
+        .iter()
+        .filter(|(_, _, _, _, alive, _, _)| alive.is_some())
+        .map(|(_, _, _, transform, _, _, _)| transform.translation.truncate())
+        .collect();
+    let mut taken: Vec<(usize, usize)> = players
+        .iter()
+        .map(|(_, id, slot, _, _, _, _)| (id.0, slot.0))
+        .collect();
+    let mut taken_colors: Vec<PlayerColor> = players
+        .iter()
+        .filter_map(|(.., color)| color.copied())
+        .collect();
+    for input in &tick_inputs.players {
+        if !input.pressed.contains(&GamepadButtonType::Start)
+            || taken.iter().any(|(id, _)| *id == input.id)
+        {
+            continue;
+        }
+        let Some(slot) = (0..MAX_PLAYERS).find(|slot| taken.iter().all(|(_, s)| s != slot)) else {
+            continue;
+        };
+        taken.push((input.id, slot));
+        let controller_name = controllers
+            .0
+            .iter()
+            .find(|controller| controller.id == input.id)
+            .map_or_else(|| format!("Controller {}", input.id), |c| c.name.clone());
+
+        let spawn_point = spawn_selector.choose(
+            &mut rng.0,
+            input.id,
+            &enemies,
+            player_config.min_spawn_distance,
+            player_config.scale,
+        );
+        // Players joining on the same tick keep their distance from each other too.
+        enemies.push(spawn_point);
+        let color = PlayerColor::first_free(&taken_colors);
+        taken_colors.push(color);
+        let material_handle = materials.add(ColorMaterial::from(color.color()));
+        let mut player_commands = commands.spawn((
+            MaterialMesh2dBundle {
+                mesh: player_mesh.mesh_handle.clone().into(),
+                material: material_handle.clone(),
+                transform: Transform::from_translation(spawn_point.extend(input.id as f32))
+                    .with_scale(Vec3::new(player_config.scale, player_config.scale, 0.0))
+                    .with_rotation(Quat::from_rotation_z(rng.gen_range(0.0..2.0 * PI))),
+                ..default()
+            },
+            Player { material_handle },
+            Collider,
+            ID(input.id),
+            Slot(slot),
+            PlayerName::for_slot(slot),
+            color,
+            Health {
+                current_health: player_config.starting_health,
+            },
+            Shooter {
+                timer: Timer::from_seconds(player_config.shooting_delay, TimerMode::Repeating),
+            },
+            Alive,
+            Name::new(format!("Player: {controller_name}")),
+            Controller {
+                name: controller_name,
+            },
+        ));
+        if player_config.spawn_protection > 0.0 {
+            player_commands.insert(SpawnProtection::new(player_config.spawn_protection));
+        }
+    }
+
+    for (entity, id, _, _, _, leaving, _) in &mut players {
+        let holding = tick_inputs
+            .player(id.0)
+            .is_some_and(|input| input.held.contains(&GamepadButtonType::Select));
+        match (holding, leaving) {
+            (true, Some(mut leaving)) => {
+                leaving.timer.tick(fixed_time.period);
+                if leaving.timer.finished() {
+                    commands.entity(entity).despawn();
+                    despawn_death_markers(&mut commands, &death_markers, id.0);
+                }
+            }
+            (true, None) => {
+                commands.entity(entity).insert(Leaving {
+                    timer: Timer::from_seconds(LEAVE_HOLD_SECONDS, TimerMode::Once),
+                });
+            }
+            (false, Some(_)) => {
+                commands.entity(entity).remove::<Leaving>();
+            }
+            (false, None) => (),
+        }
+    }
+}
+
+pub fn player_movement(
+    mut players: Query<
+        (&mut Transform, &ID, Option<&PlayerOverrides>),
+        (With<Player>, With<Alive>),
+    >,
+    tick_inputs: Res<TickInputs>,
+    fixed_time: Res<FixedTime>,
+    arena: Res<Arena>,
+    player_config: Res<PlayerConfig>,
+) {
+    for (mut transform, id, overrides) in &mut players {
+        let Some(input) = tick_inputs.player(id.0) else {
+            continue;
+        };
+        let speed = player_config.with_overrides(overrides).speed;
+        let movement_amount = speed * fixed_time.period.as_secs_f32();
+        let mut v = input.movement;
+        if v.distance(Vec2::ZERO) > 1.0 {
+            v = v.normalize();
+        }
+        transform.translation.x += movement_amount * v.x;
+        transform.translation.y += movement_amount * v.y;
+        let bounds = arena.half_size.extend(f32::MAX);
+        transform.translation = transform.translation.clamp(-bounds, bounds);
+    }
+}
+
+pub fn player_rotation(
+    mut players: Query<(&mut Transform, &ID), (With<Player>, With<Alive>)>,
+    tick_inputs: Res<TickInputs>,
+    fixed_time: Res<FixedTime>,
+    player_config: Res<PlayerConfig>,
+) {
+    for (mut transform, id) in &mut players {
+        let Some(input) = tick_inputs.player(id.0) else {
+            continue;
+        };
+        let v = input.aim;
+        if v != Vec2::ZERO {
+            let target_quat = Quat::from_rotation_z(-v.angle_between(Vec2::X) - PI / 2.0);
+            let angle_between = transform.rotation.angle_between(target_quat);
+            let max_angle = player_config.turning_speed * fixed_time.period.as_secs_f32();
+            if angle_between > max_angle {
+                let s = max_angle / angle_between;
+                transform.rotation = transform.rotation.slerp(target_quat, s);
+            } else {
+                transform.rotation = target_quat;
+            };
+        }
+    }
+}
+
+pub fn respond_to_player_config_change(
+    mut ev_player_config_changed: EventReader<PlayerConfigChanged>,
+    mut shooters: Query<
+        (
+            Entity,
+            &mut Shooter,
+            &mut Transform,
+            &mut Health,
+            Option<&Alive>,
+            Option<&PlayerOverrides>,
+        ),
+        With<Player>,
+    >,
+    player_config: Res<PlayerConfig>,
+    mut commands: Commands,
+) {
+    for ev in ev_player_config_changed.iter() {
+        for (shooter_entity, mut shooter, mut transform, mut health, alive, overrides) in
+            &mut shooters
+        {
+            let player_config = player_config.with_overrides(overrides);
+            match ev.0 {
+                PlayerSetting::ShootingDelay => shooter
+                    .timer
+                    .set_duration(Duration::from_secs_f32(player_config.shooting_delay)),
+                PlayerSetting::Scale => {
+                    transform.scale = Vec3 {
+                        x: player_config.scale,
+                        y: player_config.scale,
+                        z: 0.0,
+                    }
+                }
+                // Dead players stay out of collisions until they respawn.
+                PlayerSetting::Invincible if player_config.invincible || alive.is_none() => {
+                    commands.entity(shooter_entity).remove::<Collider>();
+                }
+                PlayerSetting::Invincible => {
+                    commands.entity(shooter_entity).insert(Collider);
+                }
+                // Changing the health mid-match heals no one, it only caps it.
+                PlayerSetting::StartingHealth => {
+                    health.current_health = health.current_health.min(player_config.starting_health)
+                }
+                // Read from the config wherever they are used.
+                PlayerSetting::Speed
+                | PlayerSetting::TurningSpeed
+                | PlayerSetting::MinSpawnDistance
+                | PlayerSetting::SpawnProtection
+                | PlayerSetting::ReconnectGrace => (),
+            }
+        }
+    }
+}
+
+/// Applies handicaps edited in the settings window.
+pub fn apply_player_overrides(
+    tick_inputs: Res<TickInputs>,
+    mut players: Query<(Entity, &ID, &mut Shooter, &mut Transform, &mut Health), With<Player>>,
+    player_config: Res<PlayerConfig>,
+    mut commands: Commands,
+) {
+    for (id, overrides) in &tick_inputs.player_overrides {
+        for (entity, player_id, mut shooter, mut transform, mut health) in &mut players {
+            if player_id.0 != *id {
+                continue;
+            }
+            let config = player_config.with_overrides(Some(overrides));
+            shooter
+                .timer
+                .set_duration(Duration::from_secs_f32(config.shooting_delay));
+            transform.scale = Vec3::new(config.scale, config.scale, 0.0);
+            health.current_health = health.current_health.min(config.starting_health);
+            commands.entity(entity).insert(overrides.clone());
+        }
+    }
+}
+
+/// Hides dead players and takes them out of collisions, leaving a cross where they fell.
+pub fn kill_player(
+    mut ev_player_died: EventReader<PlayerDied>,
+    players: Query<(Entity, &ID, &Transform, &Player), With<Alive>>,
+    player_mesh: Res<PlayerMesh>,
+    mut commands: Commands,
+) {
+    // A player can only die once, however many ways they died this tick.
+    let victims: HashSet<usize> = ev_player_died.iter().map(|ev| ev.victim).collect();
+    for (entity, id, transform, player) in &players {
+        if !victims.contains(&id.0) {
+            continue;
+        }
+        commands
+            .entity(entity)
+            .remove::<(Alive, Collider, SpawnProtection)>()
+            .insert(Visibility::Hidden);
+        spawn_death_marker(
+            &mut commands,
+            &player_mesh,
+            player.material_handle.clone(),
+            id.0,
+            transform.translation.truncate(),
+            transform.scale.x,
+        );
+    }
+}
+
+/// Leaves a cross the size of a player where the player with this ID fell.
+pub fn spawn_death_marker(
+    commands: &mut Commands,
+    player_mesh: &PlayerMesh,
+    material: Handle<ColorMaterial>,
+    id: usize,
+    position: Vec2,
+    size: f32,
+) {
+    commands
+        .spawn((
+            SpatialBundle::from_transform(Transform::from_translation(
+                // Below every player, which sit at z = ID.
+                position.extend(-1.0),
+            )),
+            DeathMarker { id },
+            Name::new("Death marker"),
+        ))
+        .with_children(|parent| {
+            for angle in [PI / 4.0, -PI / 4.0] {
+                parent.spawn(MaterialMesh2dBundle {
+                    mesh: player_mesh.mesh_handle.clone().into(),
+                    material: material.clone(),
+                    transform: Transform::from_rotation(Quat::from_rotation_z(angle))
+                        .with_scale(Vec3::new(size, size / 5.0, 0.0)),
+                    ..default()
+                });
+            }
+        });
+}
+
+fn despawn_death_markers(
+    commands: &mut Commands,
+    death_markers: &Query<(Entity, &DeathMarker)>,
+    id: usize,
+) {
+    for (entity, death_marker) in death_markers {
+        if death_marker.id == id {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
+
+pub fn handle_buttons(
+    tick_inputs: Res<TickInputs>,
+    mut players: Query<
+        (
+            Entity,
+            &ID,
+            Option<&Alive>,
+            &mut Transform,
+            &mut Health,
+            Option<&PlayerOverrides>,
+        ),
+        With<Player>,
+    >,
+    death_markers: Query<(Entity, &DeathMarker)>,
+    mut commands: Commands,
+    spawn_selector: SpawnSelector,
+    mut rng: ResMut<GameRng>,
+    player_config: Res<PlayerConfig>,
+    mut ev_player_died: EventWriter<PlayerDied>,
+) {
+    let living: Vec<(usize, Vec2)> = players
+        .iter()
+        .filter(|(_, _, alive, _, _, _)| alive.is_some())
+        .map(|(_, id, _, transform, _, _)| (id.0, transform.translation.truncate()))
+        .collect();
+    // In ID order, since respawning draws from the RNG.
+    let mut in_order: Vec<(usize, Entity)> = players
+        .iter()
+        .map(|(entity, id, ..)| (id.0, entity))
+        .collect();
+    in_order.sort();
+    for (_, entity) in in_order {
+        let (entity, id, alive_option, mut transform, mut health, overrides) =
+            players.get_mut(entity).unwrap();
+        let player_config = player_config.with_overrides(overrides);
+        let Some(input) = tick_inputs.player(id.0) else {
+            continue;
+        };
+        // However many times it was pressed, Mode does one thing a tick.
+        if input.pressed.contains(&GamepadButtonType::Mode) {
+            match alive_option {
+                Some(_) => {
+                    // Running their health out means no hit later in the tick kills them again.
+                    health.current_health = 0;
+                    ev_player_died.send(PlayerDied {
+                        victim: id.0,
+                        killer: None,
+                    });
+                }
+                None => {
+                    commands
+                        .entity(entity)
+                        .insert((Alive, Visibility::Inherited));
+                    if !player_config.invincible {
+                        commands.entity(entity).insert(Collider);
+                    }
+                    if player_config.spawn_protection > 0.0 {
+                        commands
+                            .entity(entity)
+                            .insert(SpawnProtection::new(player_config.spawn_protection));
+                    }
+                    despawn_death_markers(&mut commands, &death_markers, id.0);
+                    let enemies: Vec<Vec2> = living
+                        .iter()
+                        .filter(|(other_id, _)| *other_id != id.0)
+                        .map(|(_, position)| *position)
+                        .collect();
+                    let spawn_point = spawn_selector.choose(
+                        &mut rng.0,
+                        id.0,
+                        &enemies,
+                        player_config.min_spawn_distance,
+                        player_config.scale,
+                    );
+                    transform.translation.x = spawn_point.x;
+                    transform.translation.y = spawn_point.y;
+                    health.current_health = player_config.starting_health;
+                }
+            }
+        }
+    }
+}
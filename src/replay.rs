@@ -0,0 +1,352 @@
+//! Recording of every tick's [`TickInputs`] to a replay file, and playback of a replay through
+//! the same gameplay systems with pause, scrub and speed controls.
+//!
+//! The simulation is deterministic given the RNG seed, the starting configs and the inputs of
+//! every tick, so that is all a replay stores.
+
+use std::{
+    fs::{self, File},
+    io::{self, BufReader, BufWriter},
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use bevy::{app::AppExit, prelude::*};
+use bevy_inspector_egui::{bevy_egui::EguiContexts, egui, egui::Slider};
+use bincode::Options;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    arena::{Arena, Map},
+    bot::drive_bots,
+    combat::{Bullet, BulletConfig, PlayerDied, PlayerHit, PlayerShot},
+    input::{read_live_inputs, PendingInputs, TickInputs},
+    kill_feed::{KillFeedEntry, KillTracker},
+    net::read_client_inputs,
+    player::{Controllers, DeathMarker, Player, PlayerConfig, PlayerConfigChanged},
+    settings::data_dir,
+    stats::MatchStats,
+    GameRng, GameSet,
+};
+
+const REPLAY_VERSION: u32 = 1;
+const REPLAY_DIRECTORY: &str = "replays";
+const SEEK_STEP_SECONDS: f32 = 5.0;
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Replay {
+    pub version: u32,
+    pub seed: u64,
+    pub tick_period: Duration,
+    pub player_config: PlayerConfig,
+    pub bullet_config: BulletConfig,
+    pub map: Map,
+    pub ticks: Vec<TickInputs>,
+}
+
+impl Replay {
+    fn options() -> impl Options {
+        bincode::DefaultOptions::new()
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let writer = BufWriter::new(File::create(path)?);
+        Self::options()
+            .serialize_into(writer, self)
+            .map_err(|err| io::Error::new(io::ErrorKind::Other, err))
+    }
+
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let reader = BufReader::new(File::open(path)?);
+        let replay: Replay = Self::options()
+            .deserialize_from(reader)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        if replay.version != REPLAY_VERSION {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("unsupported replay version {}", replay.version),
+            ));
+        }
+        Ok(replay)
+    }
+}
+
+/// Records the match when `playback` is `None`, otherwise plays the given replay.
+pub struct ReplayPlugin {
+    pub playback: Option<Replay>,
+    /// The seed to record with, random if `None`.
+    pub seed: Option<u64>,
+}
+
+impl Plugin for ReplayPlugin {
+    fn build(&self, app: &mut App) {
+        match &self.playback {
+            Some(replay) => {
+                let replay = replay.clone();
+                app.insert_resource(GameRng(StdRng::seed_from_u64(replay.seed)))
+                    .insert_resource(FixedTime::new(replay.tick_period))
+                    .insert_resource(replay.player_config.clone())
+                    .insert_resource(replay.bullet_config.clone())
+                    .insert_resource(replay.map)
+                    .insert_resource(ReplayPlayback {
+                        replay,
+                        cursor: 0,
+                        speed: 1.0,
+                        seek_to: None,
+                    })
+                    .add_systems(FixedUpdate, read_replay_inputs.in_set(GameSet::ReadInputs))
+                    .add_systems(
+                        Update,
+                        (replay_ui_system, replay_keyboard_controls, seek_replay).chain(),
+                    );
+            }
+            None => {
+                let seed = self.seed.unwrap_or_else(|| rand::thread_rng().gen());
+                app.insert_resource(GameRng(StdRng::seed_from_u64(seed)))
+                    .insert_resource(RecordingSeed(seed))
+                    .add_systems(Startup, start_recording)
+                    .add_systems(
+                        FixedUpdate,
+                        record_tick_inputs
+                            .in_set(GameSet::ReadInputs)
+                            .after(read_live_inputs)
+                            .after(drive_bots)
+                            .after(read_client_inputs),
+                    )
+                    .add_systems(Last, save_recording);
+            }
+        }
+    }
+}
+
+pub fn is_playing_back(playback: Option<Res<ReplayPlayback>>) -> bool {
+    playback.is_some()
+}
+
+#[derive(Resource)]
+struct RecordingSeed(u64);
+
+#[derive(Resource)]
+struct ReplayRecorder {
+    replay: Replay,
+    /// Where the session is saved, in the replays directory in the user's data directory.
+    path: Option<PathBuf>,
+}
+
+fn start_recording(
+    mut commands: Commands,
+    seed: Res<RecordingSeed>,
+    fixed_time: Res<FixedTime>,
+    player_config: Res<PlayerConfig>,
+    bullet_config: Res<BulletConfig>,
+    map: Res<Map>,
+) {
+    commands.insert_resource(ReplayRecorder {
+        replay: Replay {
+            version: REPLAY_VERSION,
+            seed: seed.0,
+            tick_period: fixed_time.period,
+            player_config: player_config.clone(),
+            bullet_config: bullet_config.clone(),
+            map: *map,
+            ticks: vec![],
+        },
+        path: data_dir().map(|directory| {
+            let timestamp = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_millis();
+            directory
+                .join(REPLAY_DIRECTORY)
+                .join(format!("session-{timestamp}.replay"))
+        }),
+    });
+}
+
+fn record_tick_inputs(mut recorder: ResMut<ReplayRecorder>, tick_inputs: Res<TickInputs>) {
+    recorder.replay.ticks.push(tick_inputs.clone());
+}
+
+/// Saves the session so far whenever a match ends, so that a crash loses at most the match
+/// being played, and again when the game closes.
+fn save_recording(
+    recorder: Res<ReplayRecorder>,
+    stats: Res<MatchStats>,
+    mut ev_app_exit: EventReader<AppExit>,
+    mut match_on: Local<bool>,
+) {
+    let ended = std::mem::replace(&mut *match_on, stats.match_on) && !stats.match_on;
+    let closing = ev_app_exit.iter().next().is_some();
+    if (!ended && !closing) || recorder.replay.ticks.is_empty() {
+        return;
+    }
+    let Some(path) = &recorder.path else {
+        warn!("nowhere to save the replay");
+        return;
+    };
+    let directory = path.parent().unwrap_or(Path::new("."));
+    match fs::create_dir_all(directory).and_then(|_| recorder.replay.save(path)) {
+        Ok(()) => info!("saved replay to {}", path.display()),
+        Err(err) => error!("failed to save replay to {}: {err}", path.display()),
+    }
+}
+
+#[derive(Resource)]
+pub struct ReplayPlayback {
+    replay: Replay,
+    cursor: usize,
+    speed: f32,
+    seek_to: Option<usize>,
+}
+
+impl ReplayPlayback {
+    fn ticks_per_second(&self) -> f32 {
+        1.0 / self.replay.tick_period.as_secs_f32()
+    }
+}
+
+fn read_replay_inputs(
+    mut playback: ResMut<ReplayPlayback>,
+    mut tick_inputs: ResMut<TickInputs>,
+    mut time: ResMut<Time>,
+) {
+    match playback.replay.ticks.get(playback.cursor) {
+        Some(recorded) => {
+            *tick_inputs = recorded.clone();
+            playback.cursor += 1;
+        }
+        None => {
+            *tick_inputs = TickInputs::default();
+            time.pause();
+        }
+    }
+}
+
+fn replay_ui_system(
+    mut contexts: EguiContexts,
+    mut playback: ResMut<ReplayPlayback>,
+    mut time: ResMut<Time>,
+) {
+    let ticks_per_second = playback.ticks_per_second();
+    let total = playback.replay.ticks.len();
+    egui::Window::new("Replay").show(contexts.ctx_mut(), |ui| {
+        ui.horizontal(|ui| {
+            let label = if time.is_paused() { "Play" } else { "Pause" };
+            if ui.button(label).clicked() {
+                toggle_pause(&mut time);
+            }
+            ui.label(format!(
+                "{:.1}s / {:.1}s",
+                playback.cursor as f32 / ticks_per_second,
+                total as f32 / ticks_per_second
+            ));
+        });
+        let mut position = playback.seek_to.unwrap_or(playback.cursor);
+        if ui
+            .add(Slider::new(&mut position, 0..=total).text("tick"))
+            .changed()
+        {
+            playback.seek_to = Some(position);
+        }
+        if ui
+            .add(
+                Slider::new(&mut playback.speed, 0.25..=8.0)
+                    .logarithmic(true)
+                    .text("speed"),
+            )
+            .changed()
+        {
+            time.set_relative_speed(playback.speed);
+        }
+    });
+}
+
+fn replay_keyboard_controls(
+    keys: Res<Input<KeyCode>>,
+    mut playback: ResMut<ReplayPlayback>,
+    mut time: ResMut<Time>,
+) {
+    if keys.just_pressed(KeyCode::Space) {
+        toggle_pause(&mut time);
+    }
+    let step = (SEEK_STEP_SECONDS * playback.ticks_per_second()) as usize;
+    if keys.just_pressed(KeyCode::Left) {
+        playback.seek_to = Some(playback.cursor.saturating_sub(step));
+    }
+    if keys.just_pressed(KeyCode::Right) {
+        playback.seek_to = Some(playback.cursor + step);
+    }
+    if keys.just_pressed(KeyCode::Up) {
+        playback.speed = (playback.speed * 2.0).min(8.0);
+        time.set_relative_speed(playback.speed);
+    }
+    if keys.just_pressed(KeyCode::Down) {
+        playback.speed = (playback.speed / 2.0).max(0.25);
+        time.set_relative_speed(playback.speed);
+    }
+}
+
+fn toggle_pause(time: &mut Time) {
+    if time.is_paused() {
+        time.unpause();
+    } else {
+        time.pause();
+    }
+}
+
+/// Jumps to `seek_to` by resimulating: going backwards restarts the match from the seed and
+/// starting configs, then ticks are run as fast as possible up to the target.
+fn seek_replay(world: &mut World) {
+    let Some(target) = world.resource_mut::<ReplayPlayback>().seek_to.take() else {
+        return;
+    };
+    let target = target.min(world.resource::<ReplayPlayback>().replay.ticks.len());
+    if target < world.resource::<ReplayPlayback>().cursor {
+        restart_replay(world);
+    }
+    while world.resource::<ReplayPlayback>().cursor < target {
+        world.run_schedule(FixedUpdate);
+    }
+    // The simulation has seen the deaths skipped over, but the kill feed shouldn't.
+    world.resource_mut::<Events<PlayerDied>>().clear();
+}
+
+/// Puts the simulation back the way it was before the first tick, so that nothing from the
+/// ticks already played carries over into playing them again.
+fn restart_replay(world: &mut World) {
+    let entities: Vec<Entity> = world
+        .query_filtered::<Entity, Or<(
+            With<Player>,
+            With<Bullet>,
+            With<DeathMarker>,
+            // Kills that haven't happened yet.
+            With<KillFeedEntry>,
+        )>>()
+        .iter(world)
+        .collect();
+    for entity in entities {
+        world.entity_mut(entity).despawn_recursive();
+    }
+    let mut playback = world.resource_mut::<ReplayPlayback>();
+    playback.cursor = 0;
+    let seed = playback.replay.seed;
+    let player_config = playback.replay.player_config.clone();
+    let bullet_config = playback.replay.bullet_config.clone();
+    world.insert_resource(GameRng(StdRng::seed_from_u64(seed)));
+    world.insert_resource(Controllers::default());
+    world.insert_resource(player_config);
+    world.insert_resource(bullet_config);
+    // The first recorded tick sets the arena again.
+    world.insert_resource(Arena::default());
+    world.insert_resource(TickInputs::default());
+    world.insert_resource(PendingInputs::default());
+    world.insert_resource(MatchStats::default());
+    if let Some(mut kill_tracker) = world.get_resource_mut::<KillTracker>() {
+        *kill_tracker = KillTracker::default();
+    }
+    world.resource_mut::<Events<PlayerShot>>().clear();
+    world.resource_mut::<Events<PlayerHit>>().clear();
+    world.resource_mut::<Events<PlayerDied>>().clear();
+    world.resource_mut::<Events<PlayerConfigChanged>>().clear();
+}
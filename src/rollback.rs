@@ -0,0 +1,1049 @@
+//! Peer-to-peer matches with rollback netcode, in the style of GGPO. Every machine runs the
+//! whole simulation and sends the others what its controllers did each tick. Rather than
+//! waiting for everyone's inputs, a tick is simulated straight away, guessing that inputs still
+//! on their way are the same as the last ones. When they arrive and the guess was wrong, the
+//! world is put back the way it was saved before that tick, and the ticks since are simulated
+//! again with the real inputs.
+//!
+//! That only works because the simulation is deterministic: everything follows from
+//! [`TickInputs`], the seed and the configs. To catch machines drifting apart anyway, peers
+//! compare checksums of the states that nobody's inputs can change any more.
+//!
+//! Every machine lists the peers in the same order, which gives players the same IDs
+//! everywhere. A peer that stops responding holds up the match for everyone.
+
+use std::{
+    collections::{BTreeMap, VecDeque},
+    hash::{Hash, Hasher},
+    io,
+    net::{SocketAddr, UdpSocket},
+};
+
+use bevy::{
+    ecs::{
+        query::Has,
+        system::{CommandQueue, EntityCommands},
+    },
+    input::InputSystem,
+    prelude::*,
+    sprite::MaterialMesh2dBundle,
+    time::TimeSystem,
+};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    arena::{Arena, Map},
+    combat::{
+        Bullet, BulletConfig, BulletMesh, Collider, PlayerDied, PlayerHit, PlayerShot, Shooter,
+        SpawnProtection, Velocity,
+    },
+    headless::HEADLESS_ARENA_HALF_SIZE,
+    input::{
+        read_local_controllers, setup_gamepads, Connection, LocalControllers, PlayerInput,
+        TickInputs,
+    },
+    kill_feed::KillFeedCorrections,
+    lobby::{NamePicker, PlayerColor, PlayerName},
+    net::{
+        decode, encode, remote_id, sanitize, MAX_CLIENTS, MAX_CONTROLLERS_PER_CLIENT, MAX_DATAGRAM,
+    },
+    player::{
+        spawn_death_marker, Alive, Controller, Controllers, DeathMarker, Disconnected, Health,
+        Leaving, Player, PlayerConfig, PlayerMesh, PlayerOverrides, Slot, ID,
+    },
+    stats::MatchStats,
+    GameRng, GameSet,
+};
+
+/// The most machines in a match.
+pub const MAX_PEERS: usize = MAX_CLIENTS;
+/// How many ticks a peer simulates past the last one it has everyone's inputs for, before it
+/// waits for them.
+pub const MAX_PREDICTION_TICKS: u32 = 8;
+/// The most inputs sent at once, should a peer fall far behind in receiving them.
+const MAX_INPUTS_PER_MESSAGE: usize = 64;
+/// How many checksums are kept for comparing.
+const CHECKSUM_HISTORY: usize = 600;
+/// How often, in frames, a peer checks whether it is running ahead of the others.
+const TIME_SYNC_FRAMES: u32 = 60;
+
+/// Plays a peer-to-peer match. Requires the [`RollbackSession`] resource, and every peer must
+/// use the same seed and configs.
+pub struct RollbackPlugin {
+    pub seed: u64,
+}
+
+impl Plugin for RollbackPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(GameRng(StdRng::seed_from_u64(self.seed)))
+            .init_resource::<LocalControllers>()
+            .add_systems(
+                Startup,
+                setup_gamepads.run_if(resource_exists::<Gamepads>()),
+            )
+            // Before the clock, so that waiting for peers holds up this frame's ticks.
+            .add_systems(First, receive_and_roll_back.before(TimeSystem))
+            .add_systems(
+                PreUpdate,
+                read_local_controllers
+                    .after(InputSystem)
+                    .run_if(resource_exists::<Gamepads>()),
+            )
+            .add_systems(FixedUpdate, advance_rollback.in_set(GameSet::ReadInputs))
+            .add_systems(PostUpdate, send_peer_inputs);
+    }
+}
+
+/// What one machine's controllers did in a tick, by their IDs on that machine. Presses are
+/// worked out from what is held by the machine itself, since every tick's input arrives.
+#[derive(Clone, Default, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PeerInput {
+    pub connected: Vec<Connection>,
+    pub disconnected: Vec<usize>,
+    pub players: Vec<PlayerInput>,
+}
+
+impl PeerInput {
+    /// The guess for the next tick: the same sticks and buttons held, and nothing new.
+    fn predict_next(&self) -> PeerInput {
+        PeerInput {
+            connected: vec![],
+            disconnected: vec![],
+            players: self
+                .players
+                .iter()
+                .map(|input| PlayerInput {
+                    pressed: vec![],
+                    ..input.clone()
+                })
+                .collect(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct PeerMessage {
+    /// Tells apart peers that started with different seeds, configs or numbers of peers.
+    fingerprint: u64,
+    /// The next tick the sender simulates.
+    tick: u32,
+    /// The tick of the first of `inputs`.
+    first_tick: u32,
+    /// The sender's inputs that the receiver hasn't said it has.
+    inputs: Vec<PeerInput>,
+    /// How many ticks of the receiver's inputs the sender has.
+    ack: u32,
+    /// How many ticks the sender is ahead of the receiver, as far as it knows.
+    advantage: i32,
+    /// The sender's latest checksum and the tick it is from.
+    checksum: Option<(u32, u64)>,
+}
+
+struct Peer {
+    /// `None` for this machine.
+    address: Option<SocketAddr>,
+    /// The peer's inputs from the tick `first_input` on. Older ones are dropped once no peer
+    /// can need them.
+    inputs: VecDeque<PeerInput>,
+    first_input: u32,
+    /// The guesses made for ticks already simulated without the peer's input.
+    predictions: BTreeMap<u32, PeerInput>,
+    /// How many ticks of this machine's inputs the peer has.
+    acked: u32,
+    /// The latest tick the peer said it was on, and how far ahead of this machine it said it was.
+    tick: u32,
+    advantage: i32,
+    /// Checksums the peer sent, waiting to be compared.
+    checksums: BTreeMap<u32, u64>,
+}
+
+impl Peer {
+    /// How many ticks of the peer's inputs have arrived.
+    fn received(&self) -> u32 {
+        self.first_input + self.inputs.len() as u32
+    }
+
+    fn input(&self, tick: u32) -> Option<&PeerInput> {
+        let index = tick.checked_sub(self.first_input)?;
+        self.inputs.get(index as usize)
+    }
+}
+
+#[derive(Resource)]
+pub struct RollbackSession {
+    socket: UdpSocket,
+    peers: Vec<Peer>,
+    /// Which of the peers this machine is.
+    local: usize,
+    /// The next tick to simulate.
+    tick: u32,
+    /// The world before each tick that may still have to be simulated again.
+    states: BTreeMap<u32, SimState>,
+    /// Checksums of the world before ticks that everyone's inputs are in for.
+    checksums: BTreeMap<u32, u64>,
+    fingerprint: Option<u64>,
+    /// This machine's controllers as of the last tick.
+    controllers: Vec<Connection>,
+    frames: u32,
+    /// Messages held back by `latency`, with the frame to send them on.
+    outgoing: VecDeque<(u32, SocketAddr, Vec<u8>)>,
+    /// Frames left to wait for the others to catch up.
+    wait_frames: u32,
+    /// Whether the clock was paused to wait for peers.
+    waiting: bool,
+    warned_mismatch: bool,
+    /// How many frames to hold back messages, to try out a slow connection.
+    pub latency: u32,
+    /// How many times the world was rolled back.
+    pub rollbacks: u32,
+    /// The first tick where a peer's checksum differed from this machine's, if any.
+    pub desync: Option<u32>,
+}
+
+impl RollbackSession {
+    /// Plays with the peers at these addresses, in the order every peer lists them, with
+    /// `None` for this machine.
+    pub fn new(socket: UdpSocket, peers: Vec<Option<SocketAddr>>) -> io::Result<Self> {
+        let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidInput, message);
+        if !(2..=MAX_PEERS).contains(&peers.len()) {
+            return Err(invalid(&format!("a match has 2 to {MAX_PEERS} peers")));
+        }
+        let mut locals = (0..peers.len()).filter(|index| peers[*index].is_none());
+        let (Some(local), None) = (locals.next(), locals.next()) else {
+            return Err(invalid("exactly one of the peers is this machine"));
+        };
+        socket.set_nonblocking(true)?;
+        Ok(RollbackSession {
+            socket,
+            peers: peers
+                .into_iter()
+                .map(|address| Peer {
+                    address,
+                    inputs: VecDeque::new(),
+                    first_input: 0,
+                    predictions: BTreeMap::new(),
+                    acked: 0,
+                    tick: 0,
+                    advantage: 0,
+                    checksums: BTreeMap::new(),
+                })
+                .collect(),
+            local,
+            tick: 0,
+            states: BTreeMap::new(),
+            checksums: BTreeMap::new(),
+            fingerprint: None,
+            controllers: vec![],
+            frames: 0,
+            outgoing: VecDeque::new(),
+            wait_frames: 0,
+            waiting: false,
+            warned_mismatch: false,
+            latency: 0,
+            rollbacks: 0,
+            desync: None,
+        })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.socket.local_addr()
+    }
+
+    /// The next tick to simulate.
+    pub fn tick(&self) -> u32 {
+        self.tick
+    }
+
+    /// Checksums of the world before each of the latest ticks that everyone's inputs are in
+    /// for, by tick. Peers in step have the same ones.
+    pub fn checksums(&self) -> &BTreeMap<u32, u64> {
+        &self.checksums
+    }
+
+    /// Takes in the peers' messages, returning the first tick that was simulated with a wrong
+    /// guess, if any.
+    fn receive(&mut self) -> Option<u32> {
+        let mut mispredicted: Option<u32> = None;
+        let mut buffer = [0; MAX_DATAGRAM];
+        loop {
+            let (length, address) = match self.socket.recv_from(&mut buffer) {
+                Ok(received) => received,
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => break,
+                // A peer that isn't up yet can make the next receive fail.
+                Err(_) => continue,
+            };
+            let Some(message) = decode::<PeerMessage>(&buffer[..length]) else {
+                continue;
+            };
+            if Some(message.fingerprint) != self.fingerprint {
+                if !self.warned_mismatch {
+                    warn!("{address} started with a different seed, settings or peers");
+                    self.warned_mismatch = true;
+                }
+                continue;
+            }
+            let Some(peer) = self
+                .peers
+                .iter_mut()
+                .find(|peer| peer.address == Some(address))
+            else {
+                continue;
+            };
+            if message.tick >= peer.tick {
+                peer.tick = message.tick;
+                peer.advantage = message.advantage;
+            }
+            peer.acked = peer.acked.max(message.ack);
+            for (tick, input) in (message.first_tick..).zip(message.inputs) {
+                if tick != peer.received() {
+                    continue;
+                }
+                if let Some(prediction) = peer.predictions.remove(&tick) {
+                    if prediction != input {
+                        mispredicted = Some(mispredicted.map_or(tick, |first| first.min(tick)));
+                    }
+                }
+                peer.inputs.push_back(input);
+            }
+            if let Some((tick, checksum)) = message.checksum {
+                peer.checksums.insert(tick, checksum);
+            }
+        }
+        mispredicted
+    }
+
+    /// Checksums and forgets the states that can't change any more, drops inputs nobody needs
+    /// and returns whether to wait for the other peers this frame.
+    fn settle(&mut self) -> bool {
+        self.frames += 1;
+        let confirmed = self.peers.iter().map(Peer::received).min().unwrap_or(0);
+        let settled: Vec<u32> = self
+            .states
+            .range(..=confirmed)
+            .map(|(tick, _)| *tick)
+            .filter(|tick| !self.checksums.contains_key(tick))
+            .collect();
+        for tick in settled {
+            let checksum = self.states[&tick].checksum();
+            self.checksums.insert(tick, checksum);
+        }
+        while self.checksums.len() > CHECKSUM_HISTORY {
+            self.checksums.pop_first();
+        }
+        self.states.retain(|tick, _| *tick >= confirmed);
+
+        let newest = self.checksums.last_key_value().map_or(0, |(tick, _)| *tick);
+        for (index, peer) in self.peers.iter_mut().enumerate() {
+            let mut theirs = std::mem::take(&mut peer.checksums);
+            theirs.retain(|tick, checksum| match self.checksums.get(tick) {
+                Some(ours) => {
+                    if ours != checksum && self.desync.is_none() {
+                        error!("out of step with peer {} since tick {tick}", index + 1);
+                        self.desync = Some(*tick);
+                    }
+                    false
+                }
+                None => *tick > newest,
+            });
+            while theirs.len() > CHECKSUM_HISTORY {
+                theirs.pop_first();
+            }
+            peer.checksums = theirs;
+        }
+
+        let acked = self
+            .remote_peers()
+            .map(|peer| peer.acked)
+            .min()
+            .unwrap_or(confirmed);
+        // The latest input of each peer is kept for guessing the next.
+        let keep_from = confirmed.min(acked).saturating_sub(1);
+        for peer in &mut self.peers {
+            while peer.first_input < keep_from && peer.inputs.pop_front().is_some() {
+                peer.first_input += 1;
+            }
+        }
+
+        if self.tick >= confirmed + MAX_PREDICTION_TICKS {
+            return true;
+        }
+        if self.wait_frames == 0 && self.frames % TIME_SYNC_FRAMES == 0 {
+            // Half the difference, since the other peer slows down by as much as this one
+            // speeds up when it's the other way round.
+            let tick = self.tick as i32;
+            self.wait_frames = self
+                .remote_peers()
+                .map(|peer| (tick - peer.tick as i32 - peer.advantage) / 2)
+                .max()
+                .unwrap_or(0)
+                .clamp(0, MAX_PREDICTION_TICKS as i32) as u32;
+        }
+        if self.wait_frames > 0 {
+            self.wait_frames -= 1;
+            return true;
+        }
+        false
+    }
+
+    fn remote_peers(&self) -> impl Iterator<Item = &Peer> {
+        self.peers.iter().filter(|peer| peer.address.is_some())
+    }
+
+    /// This machine's input for the next tick.
+    fn local_input(&mut self, local: &LocalControllers) -> PeerInput {
+        let controllers: Vec<Connection> = local
+            .controllers
+            .iter()
+            .filter(|connection| connection.id < MAX_CONTROLLERS_PER_CLIENT)
+            .cloned()
+            .collect();
+        let last = self.peers[self.local].inputs.back();
+        let players = local
+            .players
+            .iter()
+            .filter(|input| controllers.iter().any(|c| c.id == input.id))
+            .map(|input| {
+                let held_before = last
+                    .and_then(|last| last.players.iter().find(|before| before.id == input.id))
+                    .map_or(&[][..], |before| &before.held[..]);
+                PlayerInput {
+                    id: input.id,
+                    movement: sanitize(input.movement),
+                    aim: sanitize(input.aim),
+                    pressed: input
+                        .held
+                        .iter()
+                        .filter(|button| !held_before.contains(button))
+                        .copied()
+                        .collect(),
+                    held: input.held.clone(),
+                }
+            })
+            .collect();
+        let input = PeerInput {
+            connected: controllers
+                .iter()
+                .filter(|connection| !self.controllers.contains(connection))
+                .cloned()
+                .collect(),
+            disconnected: self
+                .controllers
+                .iter()
+                .filter(|connection| controllers.iter().all(|c| c.id != connection.id))
+                .map(|connection| connection.id)
+                .collect(),
+            players,
+        };
+        self.controllers = controllers;
+        input
+    }
+
+    /// Everyone's inputs for a tick, guessing those that haven't arrived.
+    fn tick_inputs(&mut self, tick: u32) -> TickInputs {
+        let mut tick_inputs = TickInputs {
+            // Every peer's window may be a different size, so the arena is always the same.
+            arena: (tick == 0).then_some(HEADLESS_ARENA_HALF_SIZE),
+            ..default()
+        };
+        for (index, peer) in self.peers.iter_mut().enumerate() {
+            let input = match peer.input(tick) {
+                Some(input) => input.clone(),
+                None => {
+                    let prediction = peer
+                        .inputs
+                        .back()
+                        .map_or_else(PeerInput::default, PeerInput::predict_next);
+                    peer.predictions.insert(tick, prediction.clone());
+                    prediction
+                }
+            };
+            // Checked here rather than when received, so that every peer does the same.
+            let valid = |id: usize| id < MAX_CONTROLLERS_PER_CLIENT;
+            for connection in input.connected {
+                if valid(connection.id) {
+                    tick_inputs.connected.push(Connection {
+                        id: remote_id(index, connection.id),
+                        name: format!("{} (peer {})", connection.name, index + 1),
+                    });
+                }
+            }
+            for id in input.disconnected {
+                if valid(id) {
+                    tick_inputs.disconnected.push(remote_id(index, id));
+                }
+            }
+            for player in input.players {
+                if valid(player.id) {
+                    tick_inputs.players.push(PlayerInput {
+                        id: remote_id(index, player.id),
+                        movement: sanitize(player.movement),
+                        aim: sanitize(player.aim),
+                        ..player
+                    });
+                }
+            }
+        }
+        tick_inputs
+    }
+}
+
+/// FNV-1a, with integers hashed as little-endian and `usize` as 64 bits. Unlike the standard
+/// library's hasher, it gives the same hashes on every machine and with every Rust release.
+struct Fnv(u64);
+
+impl Default for Fnv {
+    fn default() -> Self {
+        Fnv(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl Hasher for Fnv {
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= u64::from(*byte);
+            self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+
+    fn write_u16(&mut self, i: u16) {
+        self.write(&i.to_le_bytes());
+    }
+
+    fn write_u32(&mut self, i: u32) {
+        self.write(&i.to_le_bytes());
+    }
+
+    fn write_u64(&mut self, i: u64) {
+        self.write(&i.to_le_bytes());
+    }
+
+    fn write_usize(&mut self, i: usize) {
+        self.write_u64(i as u64);
+    }
+
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+/// Identifies how a match started, so peers that would never agree don't play together.
+fn fingerprint(world: &World, peers: usize) -> u64 {
+    let mut hasher = Fnv::default();
+    world
+        .resource::<GameRng>()
+        .0
+        .clone()
+        .gen::<u64>()
+        .hash(&mut hasher);
+    encode(world.resource::<PlayerConfig>()).hash(&mut hasher);
+    encode(world.resource::<BulletConfig>()).hash(&mut hasher);
+    encode(world.resource::<Map>()).hash(&mut hasher);
+    world.resource::<FixedTime>().period.hash(&mut hasher);
+    peers.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Takes in the peers' inputs, rolls back and simulates again from the first tick that was
+/// guessed wrong, and pauses the clock while this machine has to wait for the others.
+pub fn receive_and_roll_back(world: &mut World) {
+    if world.resource::<RollbackSession>().fingerprint.is_none() {
+        let peers = world.resource::<RollbackSession>().peers.len();
+        let fingerprint = fingerprint(world, peers);
+        world.resource_mut::<RollbackSession>().fingerprint = Some(fingerprint);
+    }
+    if let Some(tick) = world.resource_mut::<RollbackSession>().receive() {
+        roll_back(world, tick);
+    }
+
+    let mut session = world.resource_mut::<RollbackSession>();
+    let waiting = session.settle();
+    let was_waiting = std::mem::replace(&mut session.waiting, waiting);
+    let mut time = world.resource_mut::<Time>();
+    match (was_waiting, waiting) {
+        (false, true) => time.pause(),
+        (true, false) => time.unpause(),
+        _ => (),
+    }
+}
+
+/// Puts the world back the way it was before `tick` and simulates up to where it was.
+fn roll_back(world: &mut World, tick: u32) {
+    let mut session = world.resource_mut::<RollbackSession>();
+    let state = session
+        .states
+        .get(&tick)
+        .cloned()
+        .expect("the states since everyone's inputs were last in are kept");
+    let current = std::mem::replace(&mut session.tick, tick);
+    session.rollbacks += 1;
+    let shown = recent_deaths(world.resource::<MatchStats>(), current - tick);
+    state.restore(world);
+    while world.resource::<RollbackSession>().tick < current {
+        world.run_schedule(FixedUpdate);
+        // The simulation has counted these already, and the kill feed is corrected below.
+        world.resource_mut::<Events<PlayerShot>>().clear();
+        world.resource_mut::<Events<PlayerHit>>().clear();
+        world.resource_mut::<Events<PlayerDied>>().clear();
+    }
+    let happened = recent_deaths(world.resource::<MatchStats>(), current - tick);
+    if let Some(mut corrections) = world.get_resource_mut::<KillFeedCorrections>() {
+        corrections.correct(&shown, &happened);
+    }
+}
+
+/// The deaths in the last `ticks` ticks, or in the whole match if it started since.
+fn recent_deaths(stats: &MatchStats, ticks: u32) -> Vec<PlayerDied> {
+    let since = stats.ticks.saturating_sub(ticks);
+    stats
+        .kills
+        .iter()
+        .filter(|kill| kill.tick > since)
+        .map(|kill| PlayerDied {
+            victim: kill.victim,
+            killer: kill.killer,
+        })
+        .collect()
+}
+
+/// Saves the world and feeds the tick everyone's inputs, guessing those that haven't arrived.
+pub fn advance_rollback(world: &mut World) {
+    let state = SimState::save(world);
+    let local = world.resource::<LocalControllers>().clone();
+    let mut session = world.resource_mut::<RollbackSession>();
+    let tick = session.tick;
+    // Ticks simulated again already have this machine's input.
+    if session.peers[session.local].received() == tick {
+        let input = session.local_input(&local);
+        let index = session.local;
+        session.peers[index].inputs.push_back(input);
+    }
+    session.states.insert(tick, state);
+    let tick_inputs = session.tick_inputs(tick);
+    session.tick += 1;
+    *world.resource_mut::<TickInputs>() = tick_inputs;
+}
+
+/// Sends every peer this machine's inputs that it doesn't have yet.
+pub fn send_peer_inputs(mut session: ResMut<RollbackSession>) {
+    let session = &mut *session;
+    let Some(fingerprint) = session.fingerprint else {
+        return;
+    };
+    let local = &session.peers[session.local];
+    let checksum = session
+        .checksums
+        .last_key_value()
+        .map(|(tick, checksum)| (*tick, *checksum));
+    let release = session.frames + session.latency;
+    for peer in &session.peers {
+        let Some(address) = peer.address else {
+            continue;
+        };
+        let first_tick = peer.acked.max(local.first_input);
+        let message = PeerMessage {
+            fingerprint,
+            tick: session.tick,
+            first_tick,
+            inputs: (first_tick..local.received())
+                .take(MAX_INPUTS_PER_MESSAGE)
+                .filter_map(|tick| local.input(tick).cloned())
+                .collect(),
+            ack: peer.received(),
+            advantage: session.tick as i32 - peer.tick as i32,
+            checksum,
+        };
+        session
+            .outgoing
+            .push_back((release, address, encode(&message)));
+    }
+    while let Some((release, address, datagram)) = session.outgoing.front() {
+        if *release > session.frames {
+            break;
+        }
+        // A peer that isn't up yet gets the inputs with the next message.
+        let _ = session.socket.send_to(datagram, *address);
+        session.outgoing.pop_front();
+    }
+}
+
+#[derive(Clone)]
+struct SavedPlayer {
+    id: ID,
+    player: Player,
+    transform: Transform,
+    visibility: Visibility,
+    slot: Slot,
+    name: PlayerName,
+    color: PlayerColor,
+    health: Health,
+    shooter: Shooter,
+    controller: Option<Controller>,
+    alive: bool,
+    collider: bool,
+    protection: Option<SpawnProtection>,
+    leaving: Option<Leaving>,
+    disconnected: Option<Disconnected>,
+    overrides: Option<PlayerOverrides>,
+    picker: Option<NamePicker>,
+}
+
+#[derive(Clone)]
+struct SavedBullet {
+    id: usize,
+    transform: Transform,
+    velocity: Velocity,
+    collider: bool,
+    material: Handle<ColorMaterial>,
+}
+
+#[derive(Clone)]
+struct SavedDeathMarker {
+    id: usize,
+    position: Vec2,
+    size: f32,
+    material: Handle<ColorMaterial>,
+}
+
+/// Everything the simulation keeps between ticks.
+#[derive(Clone)]
+struct SimState {
+    /// By ID.
+    players: Vec<SavedPlayer>,
+    /// By owner, then position.
+    bullets: Vec<SavedBullet>,
+    death_markers: Vec<SavedDeathMarker>,
+    rng: StdRng,
+    controllers: Controllers,
+    arena: Vec2,
+    player_config: PlayerConfig,
+    bullet_config: BulletConfig,
+    stats: MatchStats,
+}
+
+impl SimState {
+    fn save(world: &mut World) -> Self {
+        let mut players: Vec<SavedPlayer> = world
+            .query_filtered::<(
+                (
+                    &ID,
+                    &Player,
+                    &Transform,
+                    &Visibility,
+                    &Slot,
+                    &PlayerName,
+                    &PlayerColor,
+                    &Health,
+                    &Shooter,
+                    Option<&Controller>,
+                ),
+                (
+                    Has<Alive>,
+                    Has<Collider>,
+                    Option<&SpawnProtection>,
+                    Option<&Leaving>,
+                    Option<&Disconnected>,
+                    Option<&PlayerOverrides>,
+                    Option<&NamePicker>,
+                ),
+            ), With<Player>>()
+            .iter(world)
+            .map(
+                |(
+                    (
+                        id,
+                        player,
+                        transform,
+                        visibility,
+                        slot,
+                        name,
+                        color,
+                        health,
+                        shooter,
+                        controller,
+                    ),
+                    (alive, collider, protection, leaving, disconnected, overrides, picker),
+                )| SavedPlayer {
+                    id: id.clone(),
+                    player: player.clone(),
+                    transform: *transform,
+                    visibility: *visibility,
+                    slot: slot.clone(),
+                    name: name.clone(),
+                    color: *color,
+                    health: health.clone(),
+                    shooter: shooter.clone(),
+                    controller: controller.cloned(),
+                    alive,
+                    collider,
+                    protection: protection.cloned(),
+                    leaving: leaving.cloned(),
+                    disconnected: disconnected.cloned(),
+                    overrides: overrides.cloned(),
+                    picker: picker.cloned(),
+                },
+            )
+            .collect();
+        players.sort_by_key(|player| player.id.0);
+
+        let mut bullets: Vec<SavedBullet> = world
+            .query_filtered::<(
+                &ID,
+                &Transform,
+                &Velocity,
+                &Handle<ColorMaterial>,
+                Has<Collider>,
+            ), With<Bullet>>()
+            .iter(world)
+            .map(
+                |(id, transform, velocity, material, collider)| SavedBullet {
+                    id: id.0,
+                    transform: *transform,
+                    velocity: velocity.clone(),
+                    collider,
+                    material: material.clone(),
+                },
+            )
+            .collect();
+        bullets.sort_by(|a, b| {
+            let (a_position, b_position) = (a.transform.translation, b.transform.translation);
+            a.id.cmp(&b.id)
+                .then(a_position.x.total_cmp(&b_position.x))
+                .then(a_position.y.total_cmp(&b_position.y))
+        });
+
+        let markers: Vec<(usize, Vec2, Option<Entity>)> = world
+            .query::<(&DeathMarker, &Transform, Option<&Children>)>()
+            .iter(world)
+            .map(|(marker, transform, children)| {
+                let cross = children.and_then(|children| children.first().copied());
+                (marker.id, transform.translation.truncate(), cross)
+            })
+            .collect();
+        let mut death_markers: Vec<SavedDeathMarker> = markers
+            .into_iter()
+            .map(|(id, position, cross)| {
+                // The size and color are the cross's.
+                let cross = cross.map(|cross| world.entity(cross));
+                SavedDeathMarker {
+                    id,
+                    position,
+                    size: cross
+                        .and_then(|cross| cross.get::<Transform>())
+                        .map_or(0.0, |transform| transform.scale.x),
+                    material: cross
+                        .and_then(|cross| cross.get::<Handle<ColorMaterial>>())
+                        .cloned()
+                        .unwrap_or_default(),
+                }
+            })
+            .collect();
+        death_markers.sort_by(|a, b| {
+            a.id.cmp(&b.id)
+                .then(a.position.x.total_cmp(&b.position.x))
+                .then(a.position.y.total_cmp(&b.position.y))
+        });
+
+        SimState {
+            players,
+            bullets,
+            death_markers,
+            rng: world.resource::<GameRng>().0.clone(),
+            controllers: world.resource::<Controllers>().clone(),
+            arena: world.resource::<Arena>().half_size,
+            player_config: world.resource::<PlayerConfig>().clone(),
+            bullet_config: world.resource::<BulletConfig>().clone(),
+            stats: world.resource::<MatchStats>().clone(),
+        }
+    }
+
+    /// Puts the world back as it was saved. Players are kept where they still exist, so that
+    /// what is shown for them stays; bullets and death markers are spawned anew.
+    fn restore(&self, world: &mut World) {
+        world.resource_mut::<GameRng>().0 = self.rng.clone();
+        *world.resource_mut::<Controllers>() = self.controllers.clone();
+        world.resource_mut::<Arena>().half_size = self.arena;
+        if *world.resource::<PlayerConfig>() != self.player_config {
+            world.insert_resource(self.player_config.clone());
+        }
+        if *world.resource::<BulletConfig>() != self.bullet_config {
+            world.insert_resource(self.bullet_config.clone());
+        }
+        world.insert_resource(self.stats.clone());
+
+        let players: Vec<(Entity, usize)> = world
+            .query_filtered::<(Entity, &ID), With<Player>>()
+            .iter(world)
+            .map(|(entity, id)| (entity, id.0))
+            .collect();
+        let others: Vec<Entity> = world
+            .query_filtered::<Entity, Or<(With<Bullet>, With<DeathMarker>)>>()
+            .iter(world)
+            .collect();
+
+        let mut queue = CommandQueue::default();
+        let mut commands = Commands::new(&mut queue, world);
+        for entity in others {
+            commands.entity(entity).despawn_recursive();
+        }
+        for (entity, id) in &players {
+            if self.players.iter().all(|saved| saved.id.0 != *id) {
+                commands.entity(*entity).despawn();
+            }
+        }
+        let player_mesh = world.resource::<PlayerMesh>();
+        for saved in &self.players {
+            let existing = players.iter().find(|(_, id)| *id == saved.id.0);
+            let entity = match existing {
+                Some((entity, _)) => *entity,
+                None => {
+                    let controller = saved.controller.as_ref().map_or("", |c| c.name.as_str());
+                    commands
+                        .spawn((
+                            MaterialMesh2dBundle::<ColorMaterial> {
+                                mesh: player_mesh.mesh_handle.clone().into(),
+                                ..default()
+                            },
+                            Name::new(format!("Player: {controller}")),
+                        ))
+                        .id()
+                }
+            };
+            let mut player_commands = commands.entity(entity);
+            player_commands.insert((
+                saved.id.clone(),
+                saved.player.clone(),
+                saved.player.material_handle.clone(),
+                saved.transform,
+                saved.visibility,
+                saved.slot.clone(),
+                saved.name.clone(),
+                saved.color,
+                saved.health.clone(),
+                saved.shooter.clone(),
+            ));
+            insert_or_remove(&mut player_commands, saved.controller.clone());
+            insert_or_remove(&mut player_commands, saved.alive.then_some(Alive));
+            insert_or_remove(&mut player_commands, saved.collider.then_some(Collider));
+            insert_or_remove(&mut player_commands, saved.protection.clone());
+            insert_or_remove(&mut player_commands, saved.leaving.clone());
+            insert_or_remove(&mut player_commands, saved.disconnected.clone());
+            insert_or_remove(&mut player_commands, saved.overrides.clone());
+            insert_or_remove(&mut player_commands, saved.picker.clone());
+        }
+        let bullet_mesh = world.resource::<BulletMesh>();
+        for bullet in &self.bullets {
+            let mut bullet_commands = commands.spawn((
+                MaterialMesh2dBundle {
+                    mesh: bullet_mesh.mesh_handle.clone().into(),
+                    material: bullet.material.clone(),
+                    transform: bullet.transform,
+                    ..default()
+                },
+                Bullet,
+                ID(bullet.id),
+                bullet.velocity.clone(),
+                Name::new("Bullet"),
+            ));
+            if bullet.collider {
+                bullet_commands.insert(Collider);
+            }
+        }
+        for marker in &self.death_markers {
+            spawn_death_marker(
+                &mut commands,
+                player_mesh,
+                marker.material.clone(),
+                marker.id,
+                marker.position,
+                marker.size,
+            );
+        }
+        queue.apply(world);
+
+        let mut materials = world.resource_mut::<Assets<ColorMaterial>>();
+        for saved in &self.players {
+            if let Some(material) = materials.get_mut(&saved.player.material_handle) {
+                material.color = saved.color.color();
+            }
+        }
+    }
+
+    /// Sums up everything saved, the same way on every machine.
+    fn checksum(&self) -> u64 {
+        let mut hasher = Fnv::default();
+        let floats = |floats: &[f32], hasher: &mut Fnv| {
+            for float in floats {
+                float.to_bits().hash(hasher);
+            }
+        };
+        for player in &self.players {
+            player.id.0.hash(&mut hasher);
+            player.slot.0.hash(&mut hasher);
+            player.name.0.hash(&mut hasher);
+            player.color.0.hash(&mut hasher);
+            player.health.current_health.hash(&mut hasher);
+            floats(&player.transform.translation.to_array(), &mut hasher);
+            floats(&player.transform.rotation.to_array(), &mut hasher);
+            floats(&player.transform.scale.to_array(), &mut hasher);
+            (player.alive, player.collider).hash(&mut hasher);
+            player.shooter.timer.elapsed().hash(&mut hasher);
+            player.shooter.timer.duration().hash(&mut hasher);
+            let timer = |timer: Option<&Timer>| timer.map(Timer::elapsed);
+            timer(player.protection.as_ref().map(|p| &p.timer)).hash(&mut hasher);
+            timer(player.leaving.as_ref().map(|l| &l.timer)).hash(&mut hasher);
+            player
+                .disconnected
+                .as_ref()
+                .map(|d| (d.was_alive, d.timer.elapsed()))
+                .hash(&mut hasher);
+            player.overrides.as_ref().map(encode).hash(&mut hasher);
+            player.picker.as_ref().map(|p| p.cursor).hash(&mut hasher);
+            player
+                .controller
+                .as_ref()
+                .map(|c| &c.name)
+                .hash(&mut hasher);
+        }
+        for bullet in &self.bullets {
+            bullet.id.hash(&mut hasher);
+            floats(&bullet.transform.translation.to_array(), &mut hasher);
+            floats(&bullet.velocity.to_array(), &mut hasher);
+            bullet.collider.hash(&mut hasher);
+        }
+        for marker in &self.death_markers {
+            marker.id.hash(&mut hasher);
+            floats(&marker.position.to_array(), &mut hasher);
+        }
+        self.rng.clone().gen::<u64>().hash(&mut hasher);
+        let mut controllers: Vec<&Connection> = self.controllers.0.iter().collect();
+        controllers.sort_by_key(|connection| connection.id);
+        for connection in controllers {
+            (connection.id, &connection.name).hash(&mut hasher);
+        }
+        floats(&self.arena.to_array(), &mut hasher);
+        encode(&self.player_config).hash(&mut hasher);
+        encode(&self.bullet_config).hash(&mut hasher);
+        let mut stats: Vec<_> = self.stats.players.iter().collect();
+        stats.sort_by_key(|player| player.id);
+        self.stats.ticks.hash(&mut hasher);
+        for player in stats {
+            (player.id, &player.name, player.shots, player.hits).hash(&mut hasher);
+            (player.damage_taken, player.kills, player.deaths).hash(&mut hasher);
+            floats(&player.times_to_kill, &mut hasher);
+        }
+        hasher.finish()
+    }
+}
+
+/// Inserts the component if there is one and removes it if not.
+fn insert_or_remove<T: Component>(entity: &mut EntityCommands, component: Option<T>) {
+    match component {
+        Some(component) => {
+            entity.insert(component);
+        }
+        None => {
+            entity.remove::<T>();
+        }
+    }
+}
@@ -0,0 +1,322 @@
+//! Saving the settings between runs, and named presets of them.
+//!
+//! The settings in use are saved to `settings.ron` in the user's config directory on exit and
+//! loaded from there on the next start. Presets saved from the settings window go into its
+//! `presets` directory, next to the built-in ones.
+
+use std::{
+    fmt::Display,
+    fs, io,
+    ops::RangeInclusive,
+    path::{Path, PathBuf},
+};
+
+use bevy::{app::AppExit, prelude::*};
+use serde::{Deserialize, Serialize};
+
+use crate::{combat::BulletConfig, player::PlayerConfig};
+
+const SETTINGS_FILE: &str = "settings.ron";
+const PRESET_DIRECTORY: &str = "presets";
+
+// How far each setting can be set, in the settings window, the pause menu and settings files.
+pub const SPEED_RANGE: RangeInclusive<f32> = 50.0..=1000.0;
+pub const TURNING_SPEED_RANGE: RangeInclusive<f32> = 1.0..=50.0;
+pub const SHOOTING_DELAY_RANGE: RangeInclusive<f32> = 0.01..=1.0;
+pub const SCALE_RANGE: RangeInclusive<f32> = 10.0..=100.0;
+pub const HEALTH_RANGE: RangeInclusive<i32> = 1..=1000;
+pub const MIN_SPAWN_DISTANCE_RANGE: RangeInclusive<f32> = 0.0..=1000.0;
+pub const SPAWN_PROTECTION_RANGE: RangeInclusive<f32> = 0.0..=10.0;
+pub const RECONNECT_GRACE_RANGE: RangeInclusive<f32> = 0.0..=120.0;
+pub const BULLET_SPEED_RANGE: RangeInclusive<f32> = 50.0..=1500.0;
+pub const BULLET_SCALE_RANGE: RangeInclusive<f32> = 1.0..=100.0;
+
+fn out_of_range<T: Display>(name: &str, range: RangeInclusive<T>) -> String {
+    format!("{name} must be from {} to {}", range.start(), range.end())
+}
+
+/// Both configs, as they are saved to disk.
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct Settings {
+    pub player_config: PlayerConfig,
+    pub bullet_config: BulletConfig,
+}
+
+impl Settings {
+    pub fn classic() -> Self {
+        Settings {
+            player_config: PlayerConfig {
+                speed: 500.0,
+                turning_speed: 13.0,
+                shooting_delay: 0.1,
+                scale: 50.0,
+                invincible: false,
+                starting_health: 10,
+                min_spawn_distance: 300.0,
+                spawn_protection: 2.0,
+                reconnect_grace: 30.0,
+            },
+            bullet_config: BulletConfig {
+                speed: 600.0,
+                collide: true,
+                scale: 10.0,
+            },
+        }
+    }
+
+    /// The presets that come with the game, by name.
+    pub fn builtin_presets() -> Vec<(&'static str, Settings)> {
+        let classic = Settings::classic();
+        let bullet_hell = Settings {
+            player_config: PlayerConfig {
+                shooting_delay: 0.03,
+                starting_health: 40,
+                ..classic.player_config.clone()
+            },
+            bullet_config: BulletConfig {
+                speed: 400.0,
+                collide: false,
+                scale: 8.0,
+            },
+        };
+        let sniper = Settings {
+            player_config: PlayerConfig {
+                shooting_delay: 0.8,
+                scale: 40.0,
+                starting_health: 2,
+                ..classic.player_config.clone()
+            },
+            bullet_config: BulletConfig {
+                speed: 1500.0,
+                collide: true,
+                scale: 6.0,
+            },
+        };
+        let tank = Settings {
+            player_config: PlayerConfig {
+                speed: 250.0,
+                turning_speed: 6.0,
+                shooting_delay: 0.25,
+                scale: 90.0,
+                starting_health: 40,
+                ..classic.player_config.clone()
+            },
+            bullet_config: BulletConfig {
+                speed: 500.0,
+                collide: true,
+                scale: 20.0,
+            },
+        };
+        vec![
+            ("Classic", classic),
+            ("Bullet hell", bullet_hell),
+            ("Sniper", sniper),
+            ("Tank", tank),
+        ]
+    }
+
+    /// Checks every value is within what the settings window allows, describing each that
+    /// isn't.
+    pub fn validate(&self) -> Result<(), String> {
+        let player = &self.player_config;
+        let bullet = &self.bullet_config;
+        let floats = [
+            ("player speed", player.speed, SPEED_RANGE),
+            ("turning speed", player.turning_speed, TURNING_SPEED_RANGE),
+            (
+                "shooting delay",
+                player.shooting_delay,
+                SHOOTING_DELAY_RANGE,
+            ),
+            ("player size", player.scale, SCALE_RANGE),
+            (
+                "min spawn distance",
+                player.min_spawn_distance,
+                MIN_SPAWN_DISTANCE_RANGE,
+            ),
+            (
+                "spawn protection",
+                player.spawn_protection,
+                SPAWN_PROTECTION_RANGE,
+            ),
+            (
+                "reconnect grace",
+                player.reconnect_grace,
+                RECONNECT_GRACE_RANGE,
+            ),
+            ("bullet speed", bullet.speed, BULLET_SPEED_RANGE),
+            ("bullet size", bullet.scale, BULLET_SCALE_RANGE),
+        ];
+        let mut problems: Vec<String> = floats
+            .into_iter()
+            .filter(|(_, value, range)| !range.contains(value))
+            .map(|(name, _, range)| out_of_range(name, range))
+            .collect();
+        if !HEALTH_RANGE.contains(&player.starting_health) {
+            problems.push(out_of_range("health", HEALTH_RANGE));
+        }
+        match problems.is_empty() {
+            true => Ok(()),
+            false => Err(problems.join(", ")),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        if let Some(directory) = path.parent() {
+            fs::create_dir_all(directory)?;
+        }
+        let text = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+            .map_err(|err| io::Error::new(io::ErrorKind::Other, err))?;
+        fs::write(path, text)
+    }
+
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let text = fs::read_to_string(path)?;
+        ron::from_str(&text).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+    }
+
+    /// The settings saved on the last exit, or the classic ones the first time.
+    ///
+    /// This runs before the app, and so logging, is set up, so a file that can't be used is
+    /// reported on stderr.
+    pub fn load_saved() -> Self {
+        let Some(path) = config_dir().map(|directory| directory.join(SETTINGS_FILE)) else {
+            return Settings::classic();
+        };
+        let ignore = |err: &dyn std::fmt::Display| {
+            eprintln!("warning: ignoring saved settings {}: {err}", path.display());
+            Settings::classic()
+        };
+        match Settings::load(&path) {
+            Ok(settings) => match settings.validate() {
+                Ok(()) => settings,
+                Err(err) => ignore(&err),
+            },
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Settings::classic(),
+            Err(err) => ignore(&err),
+        }
+    }
+}
+
+/// Where the game keeps its settings for this user, if the platform has such a place.
+pub fn config_dir() -> Option<PathBuf> {
+    user_dir("XDG_CONFIG_HOME", ".config")
+}
+
+/// Where the game keeps what it records for this user, if the platform has such a place.
+pub fn data_dir() -> Option<PathBuf> {
+    user_dir("XDG_DATA_HOME", ".local/share")
+}
+
+/// The game's directory in one of the user's XDG base directories, which Windows and macOS
+/// don't tell apart.
+fn user_dir(xdg_variable: &str, home_default: &str) -> Option<PathBuf> {
+    let env = |name| std::env::var_os(name).filter(|value| !value.is_empty());
+    let base = if cfg!(windows) {
+        env("APPDATA").map(PathBuf::from)
+    } else if cfg!(target_os = "macos") {
+        env("HOME").map(|home| PathBuf::from(home).join("Library/Application Support"))
+    } else {
+        env(xdg_variable)
+            .map(PathBuf::from)
+            .or_else(|| env("HOME").map(|home| PathBuf::from(home).join(home_default)))
+    };
+    base.map(|base| base.join("hacker-wars"))
+}
+
+/// Saves the settings on exit and keeps the list of presets for the settings window.
+pub struct SettingsPlugin;
+
+impl Plugin for SettingsPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(Presets::load())
+            .add_systems(Last, save_settings_on_exit);
+    }
+}
+
+/// The built-in presets followed by the ones the user saved.
+#[derive(Resource, Default)]
+pub struct Presets {
+    pub presets: Vec<(String, Settings)>,
+}
+
+impl Presets {
+    fn directory() -> Option<PathBuf> {
+        config_dir().map(|directory| directory.join(PRESET_DIRECTORY))
+    }
+
+    pub fn load() -> Self {
+        let mut presets = Presets {
+            presets: Settings::builtin_presets()
+                .into_iter()
+                .map(|(name, settings)| (name.to_string(), settings))
+                .collect(),
+        };
+        let entries = Self::directory().and_then(|directory| fs::read_dir(directory).ok());
+        let mut saved: Vec<PathBuf> = entries
+            .into_iter()
+            .flatten()
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.extension().is_some_and(|extension| extension == "ron"))
+            .collect();
+        saved.sort();
+        for path in saved {
+            let Some(name) = path
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().into_owned())
+            else {
+                continue;
+            };
+            match Settings::load(&path) {
+                Ok(settings) => presets.insert(&name, settings),
+                Err(err) => warn!("ignoring preset {}: {err}", path.display()),
+            }
+        }
+        presets
+    }
+
+    /// Adds a preset, or replaces the one already called `name`.
+    fn insert(&mut self, name: &str, settings: Settings) {
+        match self.presets.iter_mut().find(|(preset, _)| preset == name) {
+            Some((_, preset)) => *preset = settings,
+            None => self.presets.push((name.to_string(), settings)),
+        }
+    }
+
+    /// Saves `settings` as the preset `name`, replacing any preset already called that.
+    pub fn save(&mut self, name: &str, settings: Settings) -> io::Result<()> {
+        let valid = |c: char| c.is_alphanumeric() || matches!(c, ' ' | '-' | '_');
+        if name.trim().is_empty() || !name.chars().all(valid) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "preset names are letters, digits, spaces, - and _",
+            ));
+        }
+        let directory = Self::directory()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no config directory"))?;
+        settings.save(&directory.join(format!("{name}.ron")))?;
+        self.insert(name, settings);
+        Ok(())
+    }
+}
+
+fn save_settings_on_exit(
+    mut ev_app_exit: EventReader<AppExit>,
+    player_config: Res<PlayerConfig>,
+    bullet_config: Res<BulletConfig>,
+) {
+    if ev_app_exit.iter().next().is_none() {
+        return;
+    }
+    let Some(path) = config_dir().map(|directory| directory.join(SETTINGS_FILE)) else {
+        return;
+    };
+    let settings = Settings {
+        player_config: player_config.clone(),
+        bullet_config: bullet_config.clone(),
+    };
+    match settings.save(&path) {
+        Ok(()) => info!("saved settings to {}", path.display()),
+        Err(err) => error!("failed to save settings to {}: {err}", path.display()),
+    }
+}
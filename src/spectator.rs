@@ -0,0 +1,277 @@
+//! Watching a match without playing in it, say on a second screen at a tournament. The camera
+//! pans and zooms freely or follows one player, and a scoreboard in the top corner lists
+//! everyone's kills, deaths and health.
+//!
+//! Keys and any controller steer the camera: the arrows, WASD or a left stick pan, the mouse
+//! wheel, + and - or the triggers zoom, Tab or the bumpers follow the next or previous player,
+//! and Space or East goes back to panning freely.
+
+use bevy::{
+    input::mouse::{MouseScrollUnit, MouseWheel},
+    prelude::*,
+};
+
+use crate::{
+    arena::Arena,
+    lobby::{PlayerColor, PlayerName},
+    player::{Alive, Health, Player, PlayerConfig, PlayerOverrides, ID},
+    stats::MatchStats,
+};
+
+/// Screen widths per second the camera pans, whatever the zoom.
+const PAN_SPEED: f32 = 0.6;
+/// How many times further the camera zooms out per second of a key or trigger held.
+const ZOOM_SPEED: f32 = 2.0;
+const MIN_ZOOM: f32 = 0.25;
+const MAX_ZOOM: f32 = 4.0;
+/// How quickly the camera catches up with the player it follows, per second.
+const FOLLOW_SPEED: f32 = 6.0;
+
+/// Requires a window. Without the controllers joining, the match has to come from elsewhere:
+/// a host, bots or a replay.
+pub struct SpectatorPlugin;
+
+impl Plugin for SpectatorPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Spectator>()
+            .add_systems(Startup, setup_scoreboard)
+            .add_systems(
+                Update,
+                (steer_spectator_camera, follow_player, update_scoreboard).chain(),
+            );
+    }
+}
+
+/// This machine only watches. Its controllers steer the camera rather than joining the match.
+#[derive(Resource, Default)]
+pub struct Spectator {
+    /// The ID of the player the camera follows, or `None` to pan freely.
+    pub following: Option<usize>,
+}
+
+impl Spectator {
+    /// Follows the player after the one followed, in the order of `ids`, wrapping around.
+    /// `ids` must be sorted.
+    pub fn follow_next(&mut self, ids: &[usize]) {
+        let next = match self.following {
+            Some(following) => ids.iter().find(|id| **id > following).or(ids.first()),
+            None => ids.first(),
+        };
+        self.following = next.copied();
+    }
+
+    /// Follows the player before the one followed, in the order of `ids`, wrapping around.
+    /// `ids` must be sorted.
+    pub fn follow_previous(&mut self, ids: &[usize]) {
+        let previous = match self.following {
+            Some(following) => ids.iter().rev().find(|id| **id < following).or(ids.last()),
+            None => ids.last(),
+        };
+        self.following = previous.copied();
+    }
+}
+
+#[derive(Component)]
+pub struct Scoreboard;
+
+pub fn setup_scoreboard(mut commands: Commands) {
+    commands.spawn((
+        TextBundle::from_section("", TextStyle::default()).with_style(Style {
+            position_type: PositionType::Absolute,
+            top: Val::Px(10.0),
+            left: Val::Px(10.0),
+            ..default()
+        }),
+        Scoreboard,
+        Name::new("Scoreboard"),
+    ));
+}
+
+pub fn steer_spectator_camera(
+    mut spectator: ResMut<Spectator>,
+    mut cameras: Query<(&mut Transform, &mut OrthographicProjection), With<Camera2d>>,
+    players: Query<&ID, With<Player>>,
+    keys: Res<Input<KeyCode>>,
+    mut ev_mouse_wheel: EventReader<MouseWheel>,
+    gamepads: Res<Gamepads>,
+    axes: Res<Axis<GamepadAxis>>,
+    buttons: Res<Input<GamepadButton>>,
+    windows: Query<&Window>,
+    arena: Res<Arena>,
+    time: Res<Time>,
+) {
+    let Ok((mut transform, mut projection)) = cameras.get_single_mut() else {
+        return;
+    };
+    let pressed = |button_type| {
+        gamepads
+            .iter()
+            .any(|gamepad| buttons.just_pressed(GamepadButton::new(gamepad, button_type)))
+    };
+    let held = |button_type| {
+        gamepads
+            .iter()
+            .any(|gamepad| buttons.pressed(GamepadButton::new(gamepad, button_type)))
+    };
+
+    let mut ids: Vec<usize> = players.iter().map(|id| id.0).collect();
+    ids.sort();
+    let shift = keys.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]);
+    if (keys.just_pressed(KeyCode::Tab) && !shift) || pressed(GamepadButtonType::RightTrigger) {
+        spectator.follow_next(&ids);
+    }
+    if (keys.just_pressed(KeyCode::Tab) && shift) || pressed(GamepadButtonType::LeftTrigger) {
+        spectator.follow_previous(&ids);
+    }
+    if keys.just_pressed(KeyCode::Space) || pressed(GamepadButtonType::East) {
+        spectator.following = None;
+    }
+
+    let key_axis = |negative: [KeyCode; 2], positive: [KeyCode; 2]| {
+        keys.any_pressed(positive) as i32 as f32 - keys.any_pressed(negative) as i32 as f32
+    };
+    let mut pan = Vec2::new(
+        key_axis([KeyCode::Left, KeyCode::A], [KeyCode::Right, KeyCode::D]),
+        key_axis([KeyCode::Down, KeyCode::S], [KeyCode::Up, KeyCode::W]),
+    );
+    for gamepad in gamepads.iter() {
+        let axis = |axis_type| {
+            axes.get(GamepadAxis::new(gamepad, axis_type))
+                .unwrap_or(0.0)
+        };
+        pan += Vec2::new(
+            axis(GamepadAxisType::LeftStickX),
+            axis(GamepadAxisType::LeftStickY),
+        );
+    }
+    let pan = pan.clamp_length_max(1.0);
+    if pan != Vec2::ZERO {
+        // Taking over the camera stops following.
+        spectator.following = None;
+        let screen_width = windows.get_single().map_or(0.0, |window| window.width());
+        let speed = PAN_SPEED * screen_width * projection.scale;
+        let position = transform.translation.truncate() + pan * speed * time.delta_seconds();
+        let position = position.clamp(-arena.half_size, arena.half_size);
+        transform.translation = position.extend(transform.translation.z);
+    }
+
+    // Positive zooms out.
+    let mut zoom = key_axis(
+        [KeyCode::Equals, KeyCode::NumpadAdd],
+        [KeyCode::Minus, KeyCode::NumpadSubtract],
+    );
+    zoom += held(GamepadButtonType::LeftTrigger2) as i32 as f32;
+    zoom -= held(GamepadButtonType::RightTrigger2) as i32 as f32;
+    let mut steps = zoom * ZOOM_SPEED.ln() * time.delta_seconds();
+    for ev in ev_mouse_wheel.iter() {
+        steps -= match ev.unit {
+            MouseScrollUnit::Line => ev.y * 0.1,
+            MouseScrollUnit::Pixel => ev.y * 0.001,
+        };
+    }
+    if steps != 0.0 {
+        projection.scale = (projection.scale * steps.exp()).clamp(MIN_ZOOM, MAX_ZOOM);
+    }
+}
+
+/// Keeps the followed player in the middle of the screen, and stops following once they leave.
+pub fn follow_player(
+    mut spectator: ResMut<Spectator>,
+    mut cameras: Query<&mut Transform, With<Camera2d>>,
+    players: Query<(&ID, &Transform), (With<Player>, Without<Camera2d>)>,
+    time: Res<Time>,
+) {
+    let Some(following) = spectator.following else {
+        return;
+    };
+    let Some((_, player_transform)) = players.iter().find(|(id, _)| id.0 == following) else {
+        spectator.following = None;
+        return;
+    };
+    let Ok(mut transform) = cameras.get_single_mut() else {
+        return;
+    };
+    let target = player_transform.translation.truncate();
+    let position = transform.translation.truncate();
+    let catch_up = 1.0 - (-FOLLOW_SPEED * time.delta_seconds()).exp();
+    transform.translation = position
+        .lerp(target, catch_up)
+        .extend(transform.translation.z);
+}
+
+/// Lists the players by kills, with the one followed marked.
+pub fn update_scoreboard(
+    mut scoreboard: Query<&mut Text, With<Scoreboard>>,
+    spectator: Res<Spectator>,
+    players: Query<
+        (
+            &ID,
+            &PlayerName,
+            &PlayerColor,
+            &Health,
+            Option<&PlayerOverrides>,
+            Option<&Alive>,
+        ),
+        With<Player>,
+    >,
+    stats: Res<MatchStats>,
+    player_config: Res<PlayerConfig>,
+) {
+    let Ok(mut text) = scoreboard.get_single_mut() else {
+        return;
+    };
+    let section = |value: String, color| {
+        TextSection::new(
+            value,
+            TextStyle {
+                font_size: 20.0,
+                color,
+                ..default()
+            },
+        )
+    };
+    let kills_and_deaths = |id: usize| {
+        stats
+            .players
+            .iter()
+            .find(|player| player.id == id)
+            .map_or((0, 0), |player| (player.kills, player.deaths))
+    };
+
+    let mut rows: Vec<_> = players.iter().collect();
+    rows.sort_by_key(|(id, ..)| {
+        let (kills, deaths) = kills_and_deaths(id.0);
+        (std::cmp::Reverse(kills), deaths, id.0)
+    });
+    let mut sections = vec![section(
+        format!(
+            "{:<18}{:>6}{:>7}{:>8}\n",
+            "SPECTATING", "KILLS", "DEATHS", "HEALTH"
+        ),
+        Color::GRAY,
+    )];
+    for (id, name, color, health, overrides, alive) in rows {
+        let (kills, deaths) = kills_and_deaths(id.0);
+        let marker = match spectator.following == Some(id.0) {
+            true => "> ",
+            false => "  ",
+        };
+        let starting_health = player_config.with_overrides(overrides).starting_health;
+        let health = match alive {
+            Some(_) => format!("{}/{starting_health}", health.current_health),
+            None => "dead".to_string(),
+        };
+        // Cut long names so the columns stay lined up.
+        let name: String = name.0.chars().take(16).collect();
+        sections.push(section(
+            format!("{marker}{name:<16}{kills:>6}{deaths:>7}{health:>8}\n"),
+            color.color(),
+        ));
+    }
+    let hint = match spectator.following {
+        Some(_) => "Tab: next player, Space: free camera",
+        None => "Tab: follow a player, arrows: pan, wheel: zoom",
+    };
+    sections.push(section(hint.to_string(), Color::GRAY));
+    text.sections = sections;
+}
@@ -0,0 +1,374 @@
+//! Everyone's shots, hits, kills and deaths over the match, kept up to date every tick, and
+//! written out as JSON and CSV when the match ends.
+
+use std::{
+    fmt,
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use bevy::{app::AppExit, ecs::query::Has, prelude::*};
+use serde::Serialize;
+
+use crate::{
+    combat::{PlayerDied, PlayerHit, PlayerShot},
+    lobby::PlayerName,
+    player::{Alive, Player, ID},
+    replay::is_playing_back,
+    settings::data_dir,
+    GameSet,
+};
+
+const STATS_DIRECTORY: &str = "stats";
+/// The fewest players in the match for there to be a match on.
+pub const MATCH_PLAYERS: usize = 2;
+
+/// The columns of [`MatchStats::write_players_csv`]'s output.
+pub const PLAYERS_CSV_HEADER: &str = "id,name,shots,hits,accuracy,damage_dealt,damage_taken,\
+                                      kills,deaths,longest_life,distance_moved";
+/// The columns of [`MatchStats::write_kills_csv`]'s output.
+pub const KILLS_CSV_HEADER: &str = "tick,seconds,killer_id,killer,victim_id,victim";
+
+pub struct StatsPlugin;
+
+impl Plugin for StatsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<MatchStats>()
+            .init_resource::<Mode>()
+            .add_systems(FixedUpdate, track_match_stats.after(GameSet::Deaths));
+    }
+}
+
+/// Writes each match's stats to the stats directory in the user's data directory when it ends,
+/// or when the game closes partway through it.
+pub struct StatsExportPlugin;
+
+impl Plugin for StatsExportPlugin {
+    fn build(&self, app: &mut App) {
+        // Watching a replay doesn't play a match.
+        app.add_systems(Last, save_stats_at_match_end.run_if(not(is_playing_back)));
+    }
+}
+
+/// How matches are played and won.
+#[derive(Resource, Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum Mode {
+    /// Players respawn after dying, and whoever has the most kills wins.
+    #[default]
+    Deathmatch,
+}
+
+impl Mode {
+    pub const ALL: [Mode; 1] = [Mode::Deathmatch];
+}
+
+impl fmt::Display for Mode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Mode::Deathmatch => "deathmatch",
+        })
+    }
+}
+
+#[derive(Clone, Default, Debug, PartialEq, Serialize)]
+pub struct PlayerStats {
+    pub id: usize,
+    pub name: String,
+    pub shots: u32,
+    /// Bullets of theirs that hit another player.
+    pub hits: u32,
+    pub accuracy: f32,
+    pub damage_dealt: u32,
+    pub damage_taken: u32,
+    pub kills: u32,
+    pub deaths: u32,
+    /// Seconds of their longest life so far, counting the one they are in.
+    pub longest_life: f32,
+    /// How far they have moved while alive.
+    pub distance_moved: f32,
+    /// For each of their kills, seconds from the victim first being hit in that life to dying.
+    pub times_to_kill: Vec<f32>,
+    /// The tick the player was first hit in their current life.
+    #[serde(skip)]
+    first_hit_tick: Option<u32>,
+    /// The tick the player's current life started, if they are alive.
+    #[serde(skip)]
+    life_start_tick: Option<u32>,
+    /// Where the player was last tick, if they were alive.
+    #[serde(skip)]
+    last_position: Option<Vec2>,
+}
+
+/// A player dying, in the order they happened.
+#[derive(Clone, Default, Debug, PartialEq, Serialize)]
+pub struct KillEvent {
+    pub tick: u32,
+    pub seconds: f32,
+    /// Whose bullet it was, or `None` if the victim took themselves out.
+    pub killer: Option<usize>,
+    pub victim: usize,
+}
+
+/// The stats of the match being played, or of the last one until the next starts.
+#[derive(Resource, Clone, Default, Debug, Serialize)]
+pub struct MatchStats {
+    pub ticks: u32,
+    pub seconds: f32,
+    pub players: Vec<PlayerStats>,
+    pub kills: Vec<KillEvent>,
+    /// Whether there are enough players for a match, as of the last tick.
+    #[serde(skip)]
+    pub match_on: bool,
+}
+
+impl MatchStats {
+    pub fn player(&mut self, id: usize) -> &mut PlayerStats {
+        let index = match self.players.iter().position(|player| player.id == id) {
+            Some(index) => index,
+            None => {
+                self.players.push(PlayerStats { id, ..default() });
+                self.players.len() - 1
+            }
+        };
+        &mut self.players[index]
+    }
+
+    /// The player with the most kills, if nobody ties with them.
+    pub fn winner(&self) -> Option<&PlayerStats> {
+        let most = self.players.iter().map(|player| player.kills).max()?;
+        let mut leaders = self.players.iter().filter(|player| player.kills == most);
+        match (leaders.next(), leaders.next()) {
+            (Some(winner), None) if most > 0 => Some(winner),
+            _ => None,
+        }
+    }
+
+    /// Adds another match's stats to these, as for a run of several matches. Its kills go on
+    /// the end of the timeline, after the ticks already counted.
+    pub fn add(&mut self, other: &MatchStats) {
+        for kill in &other.kills {
+            self.kills.push(KillEvent {
+                tick: self.ticks + kill.tick,
+                seconds: self.seconds + kill.seconds,
+                ..kill.clone()
+            });
+        }
+        self.ticks += other.ticks;
+        self.seconds += other.seconds;
+        for other in &other.players {
+            let player = self.player(other.id);
+            player.name = other.name.clone();
+            player.shots += other.shots;
+            player.hits += other.hits;
+            player.accuracy = player.hits as f32 / player.shots.max(1) as f32;
+            player.damage_dealt += other.damage_dealt;
+            player.damage_taken += other.damage_taken;
+            player.kills += other.kills;
+            player.deaths += other.deaths;
+            player.longest_life = player.longest_life.max(other.longest_life);
+            player.distance_moved += other.distance_moved;
+            player.times_to_kill.extend(&other.times_to_kill);
+        }
+    }
+
+    /// The name of the player with this ID, or an empty string for a player never seen.
+    fn name(&self, id: usize) -> &str {
+        self.players
+            .iter()
+            .find(|player| player.id == id)
+            .map_or("", |player| player.name.as_str())
+    }
+
+    pub fn write_json(&self, out: impl Write) -> io::Result<()> {
+        serde_json::to_writer_pretty(out, self)
+            .map_err(|err| io::Error::new(io::ErrorKind::Other, err))
+    }
+
+    /// Writes a row for each player, by ID, with a header.
+    pub fn write_players_csv(&self, mut out: impl Write) -> io::Result<()> {
+        writeln!(out, "{PLAYERS_CSV_HEADER}")?;
+        let mut players: Vec<_> = self.players.iter().collect();
+        players.sort_by_key(|player| player.id);
+        for player in players {
+            writeln!(
+                out,
+                "{},{},{},{},{:.3},{},{},{},{},{:.3},{:.1}",
+                player.id,
+                csv_field(&player.name),
+                player.shots,
+                player.hits,
+                player.accuracy,
+                player.damage_dealt,
+                player.damage_taken,
+                player.kills,
+                player.deaths,
+                player.longest_life,
+                player.distance_moved,
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Writes a row for each death, in order, with a header. Self-destructs have no killer.
+    pub fn write_kills_csv(&self, mut out: impl Write) -> io::Result<()> {
+        writeln!(out, "{KILLS_CSV_HEADER}")?;
+        for kill in &self.kills {
+            let (killer_id, killer) = match kill.killer {
+                Some(killer) => (killer.to_string(), csv_field(self.name(killer))),
+                None => (String::new(), String::new()),
+            };
+            writeln!(
+                out,
+                "{},{:.3},{killer_id},{killer},{},{}",
+                kill.tick,
+                kill.seconds,
+                kill.victim,
+                csv_field(self.name(kill.victim)),
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Writes `<name>.json` with everything, and `<name>.csv` and `<name>-kills.csv` with the
+    /// players and the deaths, to `directory`.
+    pub fn save(&self, directory: &Path, name: &str) -> io::Result<()> {
+        fs::create_dir_all(directory)?;
+        let create =
+            |file_name: String| File::create(directory.join(file_name)).map(BufWriter::new);
+        self.write_json(create(format!("{name}.json"))?)?;
+        self.write_players_csv(create(format!("{name}.csv"))?)?;
+        self.write_kills_csv(create(format!("{name}-kills.csv"))?)
+    }
+}
+
+pub fn track_match_stats(
+    mut stats: ResMut<MatchStats>,
+    names: Query<(&ID, &PlayerName), With<Player>>,
+    players: Query<(&ID, &Transform, Has<Alive>), With<Player>>,
+    mut ev_player_shot: EventReader<PlayerShot>,
+    mut ev_player_hit: EventReader<PlayerHit>,
+    mut ev_player_died: EventReader<PlayerDied>,
+    fixed_time: Res<FixedTime>,
+) {
+    let match_on = players.iter().count() >= MATCH_PLAYERS;
+    if match_on && !stats.match_on {
+        // Nothing from before the match, or from the last one, counts towards it.
+        *stats = MatchStats::default();
+    }
+    stats.match_on = match_on;
+    stats.ticks += 1;
+    stats.seconds = stats.ticks as f32 * fixed_time.period.as_secs_f32();
+    let tick = stats.ticks;
+    let period = fixed_time.period.as_secs_f32();
+    for (id, name) in &names {
+        let player = stats.player(id.0);
+        if player.name != name.0 {
+            player.name = name.0.clone();
+        }
+    }
+    // Players who die this tick are still alive until the end of it.
+    for (id, transform, alive) in &players {
+        let player = stats.player(id.0);
+        if !alive {
+            player.life_start_tick = None;
+            player.last_position = None;
+            continue;
+        }
+        let life_start_tick = *player.life_start_tick.get_or_insert(tick);
+        let life = (tick - life_start_tick) as f32 * period;
+        player.longest_life = player.longest_life.max(life);
+        let position = transform.translation.truncate();
+        if let Some(last_position) = player.last_position.replace(position) {
+            player.distance_moved += last_position.distance(position);
+        }
+    }
+    for ev in ev_player_shot.iter() {
+        let shooter = stats.player(ev.shooter);
+        shooter.shots += 1;
+        shooter.accuracy = shooter.hits as f32 / shooter.shots as f32;
+    }
+    // Bullets do one damage.
+    for ev in ev_player_hit.iter() {
+        let shooter = stats.player(ev.shooter);
+        shooter.hits += 1;
+        shooter.damage_dealt += 1;
+        shooter.accuracy = shooter.hits as f32 / shooter.shots.max(1) as f32;
+        let victim = stats.player(ev.victim);
+        victim.damage_taken += 1;
+        victim.first_hit_tick.get_or_insert(tick);
+    }
+    for ev in ev_player_died.iter() {
+        stats.kills.push(KillEvent {
+            tick,
+            seconds: tick as f32 * period,
+            killer: ev.killer,
+            victim: ev.victim,
+        });
+        let victim = stats.player(ev.victim);
+        victim.deaths += 1;
+        victim.life_start_tick = None;
+        victim.last_position = None;
+        let first_hit_tick = victim.first_hit_tick.take();
+        let Some(killer) = ev.killer else {
+            continue;
+        };
+        let killer = stats.player(killer);
+        killer.kills += 1;
+        if let Some(first_hit_tick) = first_hit_tick {
+            let ticks = tick - first_hit_tick;
+            killer
+                .times_to_kill
+                .push(ticks as f32 * fixed_time.period.as_secs_f32());
+        }
+    }
+}
+
+fn save_stats_at_match_end(
+    stats: Res<MatchStats>,
+    mut ev_app_exit: EventReader<AppExit>,
+    mut match_on: Local<bool>,
+    mut saved: Local<u32>,
+) {
+    let ended = std::mem::replace(&mut *match_on, stats.match_on) && !stats.match_on;
+    let closing = ev_app_exit.iter().next().is_some() && stats.match_on;
+    if !ended && !closing {
+        return;
+    }
+    *saved += 1;
+    let name = match_file_name(*saved);
+    let Some(directory) = data_dir().map(|directory| directory.join(STATS_DIRECTORY)) else {
+        warn!("nowhere to save match stats");
+        return;
+    };
+    match stats.save(&directory, &name) {
+        Ok(()) => info!(
+            "saved match stats to {}",
+            directory.join(format!("{name}.json")).display()
+        ),
+        Err(err) => error!(
+            "failed to save match stats to {}: {err}",
+            directory.display()
+        ),
+    }
+}
+
+/// A file name for the `number`th match saved this run. Matches can end within the same
+/// millisecond when nobody is watching, so the number tells them apart.
+pub(crate) fn match_file_name(number: u32) -> String {
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis();
+    format!("match-{timestamp}-{number}")
+}
+
+/// Quotes a field if it would otherwise break the row.
+pub(crate) fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
@@ -0,0 +1,1777 @@
+use std::net::UdpSocket;
+
+use bevy::{app::AppExit, ecs::event::ManualEventReader, prelude::*, time::TimeUpdateStrategy};
+use hacker_wars::{
+    arena::{Arena, Map, SpawnPoint, Wall},
+    balance::{write_csv, Balance, CSV_HEADER},
+    behavior::{Action, Personality, Situation},
+    bot::{lead_target, BotPlugin, Bots, Difficulty},
+    cli::Cli,
+    combat::{Bullet, BulletConfig, Collider, PlayerDied, Shooter, SpawnProtection, Velocity},
+    config_file::{ConfigFile, ConfigFilePlugin},
+    config_ui::{read_setting, setting_mut, SettingValue, SETTING_COUNT},
+    hud::{HudPlugin, PlayerHud},
+    input::{ConfigChange, Connection, GamepadIds, LocalControllers, PlayerInput, TickInputs},
+    join_ui::{update_join_slots, JoinSlotText},
+    kill_feed::{announce_match_start_and_end, Announcement, KillFeedCorrections, KillTracker},
+    lobby::{PlayerColor, PlayerName},
+    net::{
+        remote_id, NetClient, NetClientPlugin, NetHost, NetHostPlugin, MAX_CLIENTS,
+        REMOTE_ID_OFFSET,
+    },
+    pause_menu::{PauseMenu, PauseMenuPlugin},
+    player::{
+        Alive, Controller, DeathMarker, Disconnected, Health, Player, PlayerConfig,
+        PlayerConfigChanged, PlayerOverrides, PlayerSetting, Slot, ID, MAX_PLAYERS,
+    },
+    rollback::{RollbackPlugin, RollbackSession},
+    settings::Settings,
+    spectator::Spectator,
+    stats::{KillEvent, MatchStats, Mode, KILLS_CSV_HEADER, PLAYERS_CSV_HEADER},
+    GamePlugins, GameRng,
+};
+use rand::{rngs::StdRng, SeedableRng};
+
+const STARTING_HEALTH: i32 = 3;
+
+/// An app running the full simulation without a window, where every `app.update()` after the
+/// first advances exactly one fixed tick.
+fn test_app() -> App {
+    let mut app = App::new();
+    app.add_plugins((MinimalPlugins, AssetPlugin::default()))
+        .add_asset::<Mesh>()
+        .add_asset::<ColorMaterial>()
+        .insert_resource(TimeUpdateStrategy::ManualDuration(
+            FixedTime::default().period,
+        ))
+        .add_plugins(GamePlugins)
+        .insert_resource(PlayerConfig {
+            speed: 500.0,
+            turning_speed: 13.0,
+            // Long enough that players never fire unless a test wants them to.
+            shooting_delay: 1000.0,
+            scale: 50.0,
+            invincible: false,
+            starting_health: STARTING_HEALTH,
+            min_spawn_distance: 300.0,
+            spawn_protection: 0.0,
+            reconnect_grace: 1.0,
+        })
+        .insert_resource(BulletConfig {
+            speed: 600.0,
+            collide: true,
+            scale: 10.0,
+        })
+        .insert_resource(Arena {
+            half_size: Vec2::new(750.0, 500.0),
+        })
+        .insert_resource(GameRng(StdRng::seed_from_u64(0)));
+    // The first update runs startup and initializes the clock without ticking.
+    app.update();
+    app
+}
+
+fn spawn_player(app: &mut App, id: usize, position: Vec2) -> Entity {
+    let player_config = app.world.resource::<PlayerConfig>();
+    let scale = player_config.scale;
+    let shooting_delay = player_config.shooting_delay;
+    app.world
+        .spawn((
+            Transform::from_translation(position.extend(id as f32))
+                .with_scale(Vec3::new(scale, scale, 0.0)),
+            Player::default(),
+            Collider,
+            ID(id),
+            Health {
+                current_health: STARTING_HEALTH,
+            },
+            Shooter {
+                timer: Timer::from_seconds(shooting_delay, TimerMode::Repeating),
+            },
+            Alive,
+        ))
+        .id()
+}
+
+fn spawn_bullet(app: &mut App, owner: usize, position: Vec2) -> Entity {
+    app.world
+        .spawn((
+            Transform::from_translation(position.extend(0.0))
+                .with_scale(Vec3::new(10.0, 10.0, 0.0)),
+            Bullet,
+            ID(owner),
+            Velocity(Vec2::ZERO),
+        ))
+        .id()
+}
+
+/// Connects a controller and joins the match with it.
+fn connect(app: &mut App, id: usize, name: &str) -> Entity {
+    app.world.resource_mut::<TickInputs>().connected = vec![Connection {
+        id,
+        name: name.into(),
+    }];
+    app.world.resource_mut::<TickInputs>().players = vec![PlayerInput {
+        id,
+        pressed: vec![GamepadButtonType::Start],
+        ..default()
+    }];
+    app.update();
+    *app.world.resource_mut::<TickInputs>() = TickInputs::default();
+    app.world
+        .query::<(Entity, &ID)>()
+        .iter(&app.world)
+        .find(|(_, player_id)| player_id.0 == id)
+        .unwrap()
+        .0
+}
+
+fn disconnect(app: &mut App, id: usize) {
+    app.world.resource_mut::<TickInputs>().disconnected = vec![id];
+    app.update();
+    *app.world.resource_mut::<TickInputs>() = TickInputs::default();
+}
+
+fn health(app: &App, player: Entity) -> i32 {
+    app.world.get::<Health>(player).unwrap().current_health
+}
+
+fn press(app: &mut App, id: usize, button: GamepadButtonType) {
+    app.world.resource_mut::<TickInputs>().players = vec![PlayerInput {
+        id,
+        pressed: vec![button],
+        ..default()
+    }];
+    app.update();
+    *app.world.resource_mut::<TickInputs>() = TickInputs::default();
+}
+
+#[test]
+fn bullets_only_damage_players_other_than_their_owner() {
+    let mut app = test_app();
+    let owner = spawn_player(&mut app, 0, Vec2::new(-300.0, 0.0));
+    let enemy = spawn_player(&mut app, 1, Vec2::new(300.0, 0.0));
+    let own_bullet = spawn_bullet(&mut app, 0, Vec2::new(-300.0, 0.0));
+    let enemy_bullet = spawn_bullet(&mut app, 0, Vec2::new(300.0, 0.0));
+
+    app.update();
+
+    assert_eq!(health(&app, owner), STARTING_HEALTH);
+    assert_eq!(health(&app, enemy), STARTING_HEALTH - 1);
+    assert!(app.world.get_entity(own_bullet).is_some());
+    assert!(app.world.get_entity(enemy_bullet).is_none());
+}
+
+#[test]
+fn player_died_is_sent_exactly_once() {
+    let mut app = test_app();
+    spawn_player(&mut app, 0, Vec2::new(-300.0, 0.0));
+    let victim = spawn_player(&mut app, 1, Vec2::new(300.0, 0.0));
+    let mut reader = ManualEventReader::<PlayerDied>::default();
+    let mut deaths = vec![];
+
+    for _ in 0..STARTING_HEALTH + 2 {
+        spawn_bullet(&mut app, 0, Vec2::new(300.0, 0.0));
+        app.update();
+        let events = app.world.resource::<Events<PlayerDied>>();
+        deaths.extend(reader.iter(events).map(|ev| (ev.victim, ev.killer)));
+    }
+
+    assert_eq!(deaths, vec![(1, Some(0))]);
+    assert!(app.world.get::<Alive>(victim).is_none());
+}
+
+#[test]
+fn invincibility_removes_collider() {
+    let mut app = test_app();
+    let player = spawn_player(&mut app, 0, Vec2::ZERO);
+
+    app.world.resource_mut::<PlayerConfig>().invincible = true;
+    app.world
+        .send_event(PlayerConfigChanged(PlayerSetting::Invincible));
+    app.update();
+
+    assert!(app.world.get::<Collider>(player).is_none());
+
+    spawn_bullet(&mut app, 1, Vec2::ZERO);
+    app.update();
+
+    assert_eq!(health(&app, player), STARTING_HEALTH);
+}
+
+#[test]
+fn config_changes_only_update_what_changed() {
+    let mut app = test_app();
+    let player = spawn_player(&mut app, 0, Vec2::ZERO);
+    app.world.get_mut::<Health>(player).unwrap().current_health = 2;
+    let change = |app: &mut App, edit: fn(&mut PlayerConfig)| {
+        let old = app.world.resource::<PlayerConfig>().clone();
+        let mut new = old.clone();
+        edit(&mut new);
+        app.world.resource_mut::<TickInputs>().config = Some(ConfigChange {
+            player_settings_changed: PlayerSetting::changed(&old, &new),
+            player_config: new,
+            bullet_config: app.world.resource::<BulletConfig>().clone(),
+        });
+        app.update();
+        app.world.resource_mut::<TickInputs>().config = None;
+    };
+
+    change(&mut app, |config| config.scale = 80.0);
+    assert_eq!(app.world.get::<Transform>(player).unwrap().scale.x, 80.0);
+    assert_eq!(health(&app, player), 2);
+
+    change(&mut app, |config| config.starting_health = 50);
+    assert_eq!(health(&app, player), 2);
+
+    change(&mut app, |config| config.starting_health = 1);
+    assert_eq!(health(&app, player), 1);
+}
+
+#[test]
+fn dead_players_stop_firing() {
+    let mut app = test_app();
+    let alive = spawn_player(&mut app, 0, Vec2::new(-300.0, 0.0));
+    let dead = spawn_player(&mut app, 1, Vec2::new(300.0, 0.0));
+    app.world
+        .entity_mut(alive)
+        .get_mut::<Shooter>()
+        .unwrap()
+        .timer = Timer::from_seconds(0.05, TimerMode::Repeating);
+    app.world
+        .entity_mut(dead)
+        .get_mut::<Shooter>()
+        .unwrap()
+        .timer = Timer::from_seconds(0.05, TimerMode::Repeating);
+    app.world.entity_mut(dead).remove::<Alive>();
+
+    for _ in 0..30 {
+        app.update();
+    }
+
+    let shooters: Vec<usize> = app
+        .world
+        .query_filtered::<&ID, With<Bullet>>()
+        .iter(&app.world)
+        .map(|id| id.0)
+        .collect();
+    assert!(!shooters.is_empty());
+    assert!(shooters.iter().all(|id| *id == 0));
+}
+
+#[test]
+fn respawn_restores_starting_health() {
+    let mut app = test_app();
+    let player = spawn_player(&mut app, 0, Vec2::ZERO);
+
+    press(&mut app, 0, GamepadButtonType::Mode);
+    assert!(app.world.get::<Alive>(player).is_none());
+
+    app.world.get_mut::<Health>(player).unwrap().current_health = 0;
+    press(&mut app, 0, GamepadButtonType::Mode);
+
+    assert!(app.world.get::<Alive>(player).is_some());
+    assert_eq!(health(&app, player), STARTING_HEALTH);
+}
+
+#[test]
+fn dead_players_are_hidden_where_they_fell_until_respawn() {
+    let mut app = test_app();
+    let position = Vec2::new(120.0, -80.0);
+    let player = spawn_player(&mut app, 0, position);
+
+    press(&mut app, 0, GamepadButtonType::Mode);
+
+    let transform = app.world.get::<Transform>(player).unwrap();
+    assert_eq!(transform.translation.truncate(), position);
+    assert_eq!(
+        app.world.get::<Visibility>(player),
+        Some(&Visibility::Hidden)
+    );
+    assert!(app.world.get::<Collider>(player).is_none());
+    let markers: Vec<Vec2> = app
+        .world
+        .query::<(&DeathMarker, &Transform)>()
+        .iter(&app.world)
+        .map(|(marker, transform)| {
+            assert_eq!(marker.id, 0);
+            transform.translation.truncate()
+        })
+        .collect();
+    assert_eq!(markers, vec![position]);
+
+    press(&mut app, 0, GamepadButtonType::Mode);
+
+    assert_eq!(
+        app.world.get::<Visibility>(player),
+        Some(&Visibility::Inherited)
+    );
+    assert!(app.world.get::<Collider>(player).is_some());
+    assert_eq!(
+        app.world.query::<&DeathMarker>().iter(&app.world).count(),
+        0
+    );
+}
+
+#[test]
+fn huds_show_health_and_cooldown_and_hide_while_dead() {
+    let mut app = test_app();
+    app.add_plugins(HudPlugin);
+    let player = connect(&mut app, 0, "Pad");
+    connect(&mut app, 1, "Other");
+    app.world.get_mut::<Shooter>(player).unwrap().timer =
+        Timer::from_seconds(1.0, TimerMode::Repeating);
+    app.world
+        .get_mut::<Shooter>(player)
+        .unwrap()
+        .timer
+        .set_elapsed(std::time::Duration::from_secs_f32(0.5));
+    app.update();
+    let hud = |app: &mut App| {
+        let (hud, visibility) = app
+            .world
+            .query::<(&PlayerHud, &Visibility)>()
+            .iter(&app.world)
+            .find(|(hud, _)| hud.player == player)
+            .unwrap();
+        let fill = |bar| app.world.get::<Transform>(bar).unwrap().scale.x;
+        (fill(hud.health_bar), fill(hud.cooldown_bar), *visibility)
+    };
+
+    let (health_fill, cooldown_fill, visibility) = hud(&mut app);
+    assert_eq!(health_fill, 1.0);
+    let cooldown = app.world.get::<Shooter>(player).unwrap().timer.percent();
+    assert!(cooldown > 0.5 && cooldown < 1.0);
+    assert_eq!(cooldown_fill, cooldown);
+    assert_eq!(visibility, Visibility::Inherited);
+
+    let position = |app: &App| {
+        app.world
+            .get::<Transform>(player)
+            .unwrap()
+            .translation
+            .truncate()
+    };
+    let at = position(&app);
+    spawn_bullet(&mut app, 1, at);
+    app.update();
+    assert_eq!(health(&app, player), STARTING_HEALTH - 1);
+    let (health_fill, _, visibility) = hud(&mut app);
+    assert_eq!(
+        health_fill,
+        (STARTING_HEALTH - 1) as f32 / STARTING_HEALTH as f32
+    );
+    assert_eq!(visibility, Visibility::Inherited);
+
+    for _ in 1..STARTING_HEALTH {
+        let at = position(&app);
+        spawn_bullet(&mut app, 1, at);
+        app.update();
+    }
+    assert!(app.world.get::<Alive>(player).is_none());
+    let (health_fill, _, visibility) = hud(&mut app);
+    assert_eq!(health_fill, 0.0);
+    assert_eq!(visibility, Visibility::Hidden);
+}
+
+#[test]
+fn respawns_keep_the_minimum_distance_from_enemies() {
+    let mut app = test_app();
+    let player = spawn_player(&mut app, 0, Vec2::ZERO);
+    spawn_player(&mut app, 1, Vec2::ZERO);
+    press(&mut app, 0, GamepadButtonType::Mode);
+
+    for _ in 0..10 {
+        press(&mut app, 0, GamepadButtonType::Mode);
+        let position = app.world.get::<Transform>(player).unwrap().translation;
+        assert!(
+            position.truncate().length() >= 300.0,
+            "spawned at {position}"
+        );
+        press(&mut app, 0, GamepadButtonType::Mode);
+    }
+}
+
+#[test]
+fn respawns_use_the_safest_map_spawn_point() {
+    let mut app = test_app();
+    let near = Vec2::new(100.0, 0.0);
+    let far = Vec2::new(-600.0, 400.0);
+    for position in [near, far] {
+        app.world.spawn((
+            Transform::from_translation(position.extend(0.0)),
+            SpawnPoint,
+        ));
+    }
+    let player = spawn_player(&mut app, 0, Vec2::ZERO);
+    spawn_player(&mut app, 1, Vec2::ZERO);
+    press(&mut app, 0, GamepadButtonType::Mode);
+
+    press(&mut app, 0, GamepadButtonType::Mode);
+
+    let position = app.world.get::<Transform>(player).unwrap().translation;
+    assert_eq!(position.truncate(), far);
+}
+
+#[test]
+fn outposts_lay_out_spawn_points_that_fit_the_arena() {
+    let mut app = test_app();
+    app.insert_resource(Map::Outposts);
+    let player = connect(&mut app, 0, "Pad");
+    let spawn_points = |app: &mut App| {
+        app.world
+            .query_filtered::<&Transform, With<SpawnPoint>>()
+            .iter(&app.world)
+            .map(|transform| transform.translation.truncate())
+            .collect::<Vec<_>>()
+    };
+    let points = spawn_points(&mut app);
+    assert_eq!(points, Map::Outposts.spawn_points(Vec2::new(750.0, 500.0)));
+    assert_eq!(points.len(), 6);
+    let position = app.world.get::<Transform>(player).unwrap().translation;
+    assert!(
+        points.contains(&position.truncate()),
+        "spawned at {position}"
+    );
+
+    app.world.resource_mut::<TickInputs>().arena = Some(Vec2::new(400.0, 300.0));
+    app.update();
+    assert_eq!(
+        spawn_points(&mut app),
+        Map::Outposts.spawn_points(Vec2::new(400.0, 300.0))
+    );
+}
+
+#[test]
+fn walls_block_players_and_bullets() {
+    let mut app = test_app();
+    app.insert_resource(Map::Outposts);
+    app.update();
+    let walls: Vec<Vec2> = app
+        .world
+        .query_filtered::<&Transform, With<Wall>>()
+        .iter(&app.world)
+        .map(|transform| transform.translation.truncate())
+        .collect();
+    assert_eq!(walls.len(), 4);
+    let wall = Map::Outposts.walls(Vec2::new(750.0, 500.0))[0];
+    assert!(walls.contains(&wall.center()));
+
+    let player = spawn_player(&mut app, 0, Vec2::new(wall.min.x - 50.0, wall.center().y));
+    app.world.resource_mut::<TickInputs>().players = vec![PlayerInput {
+        id: 0,
+        movement: Vec2::X,
+        ..default()
+    }];
+    for _ in 0..20 {
+        app.update();
+    }
+    *app.world.resource_mut::<TickInputs>() = TickInputs::default();
+    let position = app.world.get::<Transform>(player).unwrap().translation;
+    // Up against the wall, half a player's size from it.
+    assert!(
+        (position.x - (wall.min.x - 25.0)).abs() < 0.01,
+        "at {position}"
+    );
+
+    let stopped = spawn_bullet(&mut app, 1, wall.center());
+    let flying = spawn_bullet(&mut app, 1, Vec2::new(wall.max.x + 20.0, wall.center().y));
+    app.update();
+    assert!(app.world.get_entity(stopped).is_none());
+    assert!(app.world.get_entity(flying).is_some());
+}
+
+#[test]
+fn spawn_protection_ignores_hits_until_it_runs_out() {
+    let mut app = test_app();
+    app.world.resource_mut::<PlayerConfig>().spawn_protection = 0.5;
+    let player = spawn_player(&mut app, 0, Vec2::ZERO);
+    press(&mut app, 0, GamepadButtonType::Mode);
+    press(&mut app, 0, GamepadButtonType::Mode);
+    assert!(app.world.get::<SpawnProtection>(player).is_some());
+    let position = app.world.get::<Transform>(player).unwrap().translation;
+
+    let bullet = spawn_bullet(&mut app, 1, position.truncate());
+    app.update();
+    assert_eq!(health(&app, player), STARTING_HEALTH);
+    // The bullet passes through rather than being spent on the protected player.
+    assert!(app.world.get_entity(bullet).is_some());
+    app.world.despawn(bullet);
+
+    for _ in 0..30 {
+        app.update();
+    }
+    assert!(app.world.get::<SpawnProtection>(player).is_none());
+    spawn_bullet(&mut app, 1, position.truncate());
+    app.update();
+    assert_eq!(health(&app, player), STARTING_HEALTH - 1);
+}
+
+#[test]
+fn firing_ends_spawn_protection() {
+    let mut app = test_app();
+    let player = spawn_player(&mut app, 0, Vec2::ZERO);
+    app.world
+        .entity_mut(player)
+        .insert(SpawnProtection::new(10.0));
+    app.world.get_mut::<Shooter>(player).unwrap().timer =
+        Timer::from_seconds(0.05, TimerMode::Repeating);
+
+    for _ in 0..10 {
+        app.update();
+    }
+    assert!(app.world.get::<SpawnProtection>(player).is_some());
+
+    app.world.resource_mut::<TickInputs>().players = vec![PlayerInput {
+        id: 0,
+        aim: Vec2::Y,
+        ..default()
+    }];
+    for _ in 0..5 {
+        app.update();
+    }
+    assert!(app.world.get::<SpawnProtection>(player).is_none());
+}
+
+#[test]
+fn overrides_replace_the_global_config_for_one_player() {
+    let mut app = test_app();
+    let handicapped = spawn_player(&mut app, 0, Vec2::new(-300.0, 0.0));
+    let normal = spawn_player(&mut app, 1, Vec2::new(300.0, 0.0));
+    app.world.resource_mut::<TickInputs>().player_overrides = vec![(
+        0,
+        PlayerOverrides {
+            speed: Some(250.0),
+            starting_health: Some(1),
+            ..default()
+        },
+    )];
+    app.update();
+    *app.world.resource_mut::<TickInputs>() = TickInputs::default();
+    assert_eq!(health(&app, handicapped), 1);
+    assert_eq!(health(&app, normal), STARTING_HEALTH);
+
+    app.world.resource_mut::<TickInputs>().players = [0, 1]
+        .map(|id| PlayerInput {
+            id,
+            movement: Vec2::Y,
+            ..default()
+        })
+        .to_vec();
+    app.update();
+    *app.world.resource_mut::<TickInputs>() = TickInputs::default();
+    let period = FixedTime::default().period.as_secs_f32();
+    let moved = |player| app.world.get::<Transform>(player).unwrap().translation.y;
+    assert!((moved(handicapped) - 250.0 * period).abs() < 1e-3);
+    assert!((moved(normal) - 500.0 * period).abs() < 1e-3);
+
+    press(&mut app, 0, GamepadButtonType::Mode);
+    press(&mut app, 0, GamepadButtonType::Mode);
+    assert_eq!(health(&app, handicapped), 1);
+}
+
+#[test]
+fn reconnecting_controllers_take_back_their_player() {
+    let mut app = test_app();
+    let player = connect(&mut app, 0, "Pad");
+    app.world.get_mut::<Health>(player).unwrap().current_health = 1;
+
+    disconnect(&mut app, 0);
+    assert!(app.world.get::<Disconnected>(player).is_some());
+    assert!(app.world.get::<Alive>(player).is_none());
+    assert!(app.world.get::<Collider>(player).is_none());
+    assert_eq!(
+        app.world.get::<Visibility>(player),
+        Some(&Visibility::Hidden)
+    );
+
+    // The controller comes back in a different slot.
+    assert_eq!(connect(&mut app, 4, "Pad"), player);
+    assert_eq!(app.world.get::<ID>(player).unwrap().0, 4);
+    assert_eq!(health(&app, player), 1);
+    assert!(app.world.get::<Disconnected>(player).is_none());
+    assert!(app.world.get::<Alive>(player).is_some());
+    assert!(app.world.get::<Collider>(player).is_some());
+    assert_eq!(app.world.query::<&Player>().iter(&app.world).count(), 1);
+}
+
+#[test]
+fn a_different_controller_given_a_freed_id_doesnt_take_over() {
+    let mut app = test_app();
+    let first = connect(&mut app, 0, "Pad");
+    let second = connect(&mut app, 1, "Stick");
+    disconnect(&mut app, 0);
+    disconnect(&mut app, 1);
+
+    // The stick comes back with the lowest free ID, which was the pad's.
+    assert_eq!(connect(&mut app, 0, "Stick"), second);
+    assert!(app.world.get::<Disconnected>(first).is_some());
+    assert!(app.world.get::<Disconnected>(second).is_none());
+    // The pad's player takes the stick's old ID until the pad comes back.
+    assert_eq!(app.world.get::<ID>(first).unwrap().0, 1);
+    assert_eq!(connect(&mut app, 1, "Pad"), first);
+    assert!(app.world.get::<Disconnected>(first).is_none());
+}
+
+#[test]
+fn disconnected_players_leave_after_the_grace_period() {
+    let mut app = test_app();
+    let player = connect(&mut app, 0, "Pad");
+    press(&mut app, 0, GamepadButtonType::Mode);
+    disconnect(&mut app, 0);
+
+    // The grace period is one second.
+    for _ in 0..50 {
+        app.update();
+    }
+    assert!(app.world.get_entity(player).is_some());
+    for _ in 0..20 {
+        app.update();
+    }
+    assert!(app.world.get_entity(player).is_none());
+    assert_eq!(
+        app.world.query::<&DeathMarker>().iter(&app.world).count(),
+        0
+    );
+}
+
+#[test]
+fn controllers_join_with_start_into_free_slots() {
+    let mut app = test_app();
+    app.world.resource_mut::<TickInputs>().connected = (0..MAX_PLAYERS + 1)
+        .map(|id| Connection {
+            id,
+            name: format!("Pad {id}"),
+        })
+        .collect();
+    app.update();
+    assert_eq!(app.world.query::<&Player>().iter(&app.world).count(), 0);
+
+    app.world.resource_mut::<TickInputs>().players = (0..MAX_PLAYERS + 1)
+        .map(|id| PlayerInput {
+            id,
+            pressed: vec![GamepadButtonType::Start],
+            ..default()
+        })
+        .collect();
+    app.update();
+    app.update();
+
+    let mut slots: Vec<(usize, usize)> = app
+        .world
+        .query::<(&ID, &Slot)>()
+        .iter(&app.world)
+        .map(|(id, slot)| (id.0, slot.0))
+        .collect();
+    slots.sort();
+    assert_eq!(slots, (0..MAX_PLAYERS).map(|i| (i, i)).collect::<Vec<_>>());
+}
+
+#[test]
+fn holding_select_leaves_the_match() {
+    let mut app = test_app();
+    let player = connect(&mut app, 0, "Pad");
+    let hold = |app: &mut App, ticks| {
+        app.world.resource_mut::<TickInputs>().players = vec![PlayerInput {
+            id: 0,
+            held: vec![GamepadButtonType::Select],
+            ..default()
+        }];
+        for _ in 0..ticks {
+            app.update();
+        }
+        *app.world.resource_mut::<TickInputs>() = TickInputs::default();
+        app.update();
+    };
+
+    // Letting go early keeps the player in.
+    hold(&mut app, 30);
+    assert!(app.world.get_entity(player).is_some());
+    hold(&mut app, 30);
+    assert!(app.world.get_entity(player).is_some());
+
+    hold(&mut app, 70);
+    assert!(app.world.get_entity(player).is_none());
+}
+
+fn press_all(app: &mut App, id: usize, buttons: &[GamepadButtonType]) {
+    for button in buttons {
+        press(app, id, *button);
+    }
+}
+
+#[test]
+fn players_never_share_a_color() {
+    let mut app = test_app();
+    let first = connect(&mut app, 0, "Pad");
+    let second = connect(&mut app, 1, "Pad");
+    let color = |app: &App, player| *app.world.get::<PlayerColor>(player).unwrap();
+    assert_eq!(color(&app, first), PlayerColor(0));
+    assert_eq!(color(&app, second), PlayerColor(1));
+
+    // Stepping forwards from the first color skips over the one that is taken.
+    press_all(
+        &mut app,
+        0,
+        &[GamepadButtonType::North, GamepadButtonType::RightTrigger],
+    );
+    assert_eq!(color(&app, first), PlayerColor(2));
+    // And backwards too.
+    press(&mut app, 0, GamepadButtonType::LeftTrigger);
+    assert_eq!(color(&app, first), PlayerColor(0));
+}
+
+#[test]
+fn names_are_spelled_with_the_d_pad() {
+    let mut app = test_app();
+    let player = connect(&mut app, 0, "Pad");
+    assert_eq!(app.world.get::<PlayerName>(player).unwrap().0, "P1");
+
+    press_all(
+        &mut app,
+        0,
+        &[
+            GamepadButtonType::North,
+            // P -> O
+            GamepadButtonType::DPadDown,
+            GamepadButtonType::DPadRight,
+            // 1 -> 2
+            GamepadButtonType::DPadUp,
+            GamepadButtonType::DPadRight,
+            // A new character, A -> B
+            GamepadButtonType::DPadUp,
+            GamepadButtonType::North,
+        ],
+    );
+
+    assert_eq!(app.world.get::<PlayerName>(player).unwrap().0, "O2B");
+}
+
+#[test]
+fn self_destructing_credits_no_killer() {
+    let mut app = test_app();
+    spawn_player(&mut app, 0, Vec2::ZERO);
+    let mut reader = ManualEventReader::<PlayerDied>::default();
+
+    press(&mut app, 0, GamepadButtonType::Mode);
+
+    let events = app.world.resource::<Events<PlayerDied>>();
+    let deaths: Vec<_> = reader
+        .iter(events)
+        .map(|ev| (ev.victim, ev.killer))
+        .collect();
+    assert_eq!(deaths, vec![(0, None)]);
+}
+
+#[test]
+fn self_destructing_and_being_shot_in_one_tick_is_one_death() {
+    let mut app = test_app();
+    spawn_player(&mut app, 0, Vec2::new(-300.0, 0.0));
+    let victim = spawn_player(&mut app, 1, Vec2::new(300.0, 0.0));
+    app.world.get_mut::<Health>(victim).unwrap().current_health = 1;
+    let mut reader = ManualEventReader::<PlayerDied>::default();
+
+    spawn_bullet(&mut app, 0, Vec2::new(300.0, 0.0));
+    app.world.resource_mut::<TickInputs>().players = vec![PlayerInput {
+        id: 1,
+        pressed: vec![GamepadButtonType::Mode, GamepadButtonType::Mode],
+        ..default()
+    }];
+    app.update();
+
+    let events = app.world.resource::<Events<PlayerDied>>();
+    let deaths: Vec<_> = reader
+        .iter(events)
+        .map(|ev| (ev.victim, ev.killer))
+        .collect();
+    assert_eq!(deaths, vec![(1, None)]);
+    let markers = app
+        .world
+        .query::<&DeathMarker>()
+        .iter(&app.world)
+        .filter(|marker| marker.id == 1)
+        .count();
+    assert_eq!(markers, 1);
+    assert_eq!(app.world.resource_mut::<MatchStats>().player(1).deaths, 1);
+}
+
+#[test]
+fn rolling_back_corrects_the_kill_feed() {
+    let died = |victim, killer| PlayerDied { victim, killer };
+    let mut corrections = KillFeedCorrections::default();
+
+    corrections.correct(
+        &[died(1, Some(0)), died(2, None), died(1, Some(0))],
+        &[died(2, None), died(3, Some(0)), died(1, Some(0))],
+    );
+
+    assert_eq!(corrections.retracted, vec![died(1, Some(0))]);
+    assert_eq!(corrections.missed, vec![died(3, Some(0))]);
+}
+
+#[test]
+fn match_stats_follow_every_life_and_export() {
+    let mut app = test_app();
+    let shooter = spawn_player(&mut app, 0, Vec2::new(-300.0, 0.0));
+    let victim_entity = spawn_player(&mut app, 1, Vec2::new(300.0, 0.0));
+    // Firing straight up, past the victim.
+    app.world.get_mut::<Shooter>(shooter).unwrap().timer =
+        Timer::from_seconds(0.05, TimerMode::Repeating);
+    for _ in 0..10 {
+        app.world.resource_mut::<TickInputs>().players = vec![PlayerInput {
+            id: 0,
+            movement: Vec2::X,
+            ..default()
+        }];
+        app.update();
+    }
+    *app.world.resource_mut::<TickInputs>() = TickInputs::default();
+    for _ in 0..STARTING_HEALTH {
+        spawn_bullet(&mut app, 0, Vec2::new(300.0, 0.0));
+        app.update();
+    }
+    app.update();
+
+    let stats = app.world.resource::<MatchStats>().clone();
+    let period = FixedTime::default().period.as_secs_f32();
+    let killer = stats.players.iter().find(|player| player.id == 0).unwrap();
+    assert!(killer.shots > 0);
+    assert_eq!((killer.hits, killer.damage_dealt, killer.kills), (3, 3, 1));
+    assert_eq!(killer.accuracy, 3.0 / killer.shots as f32);
+    // The first move comes before there is a position to measure from.
+    let step = 500.0 * period;
+    assert!((killer.distance_moved - 9.0 * step).abs() < 0.01);
+    let victim = stats.players.iter().find(|player| player.id == 1).unwrap();
+    assert_eq!((victim.damage_taken, victim.deaths), (3, 1));
+    assert_eq!(victim.distance_moved, 0.0);
+    assert!((victim.longest_life - 12.0 * period).abs() < 0.001);
+    assert_eq!(
+        stats.kills,
+        vec![KillEvent {
+            tick: 13,
+            seconds: 13.0 * period,
+            killer: Some(0),
+            victim: 1,
+        }]
+    );
+
+    let mut csv = vec![];
+    stats.write_players_csv(&mut csv).unwrap();
+    let csv = String::from_utf8(csv).unwrap();
+    let lines: Vec<_> = csv.lines().collect();
+    assert_eq!(lines.len(), 3);
+    assert_eq!(lines[0], PLAYERS_CSV_HEADER);
+    assert!(lines[2].starts_with("1,,0,0,0.000,0,3,0,1,"));
+    let mut csv = vec![];
+    stats.write_kills_csv(&mut csv).unwrap();
+    let csv = String::from_utf8(csv).unwrap();
+    assert_eq!(csv, format!("{KILLS_CSV_HEADER}\n13,0.217,0,,1,\n"));
+    let mut json = vec![];
+    stats.write_json(&mut json).unwrap();
+    let json: serde_json::Value = serde_json::from_slice(&json).unwrap();
+    assert_eq!(json["players"].as_array().unwrap().len(), 2);
+    assert_eq!(json["kills"][0]["victim"], 1);
+
+    // A run adds its matches up, with each one's kills after the ones before.
+    let mut run = stats.clone();
+    run.add(&stats);
+    assert_eq!(run.ticks, 2 * stats.ticks);
+    assert_eq!(run.kills[1].tick, stats.ticks + 13);
+    let victim = run.players.iter().find(|player| player.id == 1).unwrap();
+    assert_eq!((victim.damage_taken, victim.deaths), (6, 2));
+
+    // The match ends when the victim leaves, and its stats stay until the next one starts.
+    app.world.entity_mut(victim_entity).despawn_recursive();
+    app.update();
+    let ended = app.world.resource::<MatchStats>();
+    assert!(!ended.match_on);
+    assert_eq!(ended.kills, stats.kills);
+    spawn_player(&mut app, 1, Vec2::new(300.0, 0.0));
+    app.update();
+    let next = app.world.resource::<MatchStats>();
+    assert!(next.match_on);
+    assert_eq!(next.ticks, 1);
+    assert!(next.kills.is_empty());
+    assert!(next.players.iter().all(|player| player.shots == 0));
+}
+
+#[test]
+fn the_match_end_announces_the_stats_winner_and_ties_as_none() {
+    let mut app = test_app();
+    app.add_event::<Announcement>()
+        .init_resource::<KillTracker>()
+        .add_systems(Update, announce_match_start_and_end);
+    let mut reader = ManualEventReader::<Announcement>::default();
+    let mut announcements = |app: &mut App| {
+        let events = app.world.resource::<Events<Announcement>>();
+        reader
+            .iter(events)
+            .map(|announcement| announcement.0.clone())
+            .collect::<Vec<_>>()
+    };
+    let mut play_match = |app: &mut App, kills: [u32; 2]| {
+        spawn_player(app, 0, Vec2::new(-300.0, 0.0));
+        let player = spawn_player(app, 1, Vec2::new(300.0, 0.0));
+        app.update();
+        let mut stats = app.world.resource_mut::<MatchStats>();
+        for (id, kills) in kills.into_iter().enumerate() {
+            stats.player(id).name = format!("Player {id}");
+            stats.player(id).kills = kills;
+        }
+        app.world.entity_mut(player).despawn_recursive();
+        app.update();
+        let ended = announcements(app);
+        let survivor = app
+            .world
+            .query_filtered::<Entity, With<Player>>()
+            .single(&app.world);
+        app.world.entity_mut(survivor).despawn_recursive();
+        app.update();
+        ended
+    };
+
+    let tie = play_match(&mut app, [2, 2]);
+    assert_eq!(tie, vec!["Fight!".to_string(), "Match over".to_string()]);
+    let won = play_match(&mut app, [1, 3]);
+    assert_eq!(
+        won,
+        vec![
+            "Fight!".to_string(),
+            "Match over, Player 1 wins!".to_string()
+        ]
+    );
+}
+
+#[test]
+fn the_pause_menu_freezes_the_simulation() {
+    let mut app = test_app();
+    app.init_resource::<Input<GamepadButton>>()
+        .add_plugins(PauseMenuPlugin);
+    let player = connect(&mut app, 0, "Pad");
+    app.world.get_mut::<Shooter>(player).unwrap().timer =
+        Timer::from_seconds(1.0, TimerMode::Repeating);
+    let bullet = spawn_bullet(&mut app, 1, Vec2::new(-500.0, 0.0));
+    app.world.get_mut::<Velocity>(bullet).unwrap().0 = Vec2::X * 100.0;
+    // Platforms can number gamepads from anywhere, but the first to connect plays as 0.
+    let gamepad = Gamepad::new(20);
+    let mut ids = GamepadIds::default();
+    assert_eq!(ids.assign(gamepad, REMOTE_ID_OFFSET), Some(0));
+    app.insert_resource(ids);
+    let start = GamepadButton::new(gamepad, GamepadButtonType::Start);
+    let tap = |app: &mut App, button| {
+        app.world
+            .resource_mut::<Input<GamepadButton>>()
+            .press(button);
+        app.update();
+        let mut buttons = app.world.resource_mut::<Input<GamepadButton>>();
+        buttons.release(button);
+        buttons.clear();
+    };
+
+    tap(&mut app, start);
+    assert!(app.world.resource::<PauseMenu>().open);
+    let bullet_position = app.world.get::<Transform>(bullet).unwrap().translation;
+    let shooter_elapsed = app.world.get::<Shooter>(player).unwrap().timer.elapsed();
+    for _ in 0..10 {
+        app.update();
+    }
+    assert_eq!(
+        app.world.get::<Transform>(bullet).unwrap().translation,
+        bullet_position
+    );
+    assert_eq!(
+        app.world.get::<Shooter>(player).unwrap().timer.elapsed(),
+        shooter_elapsed
+    );
+
+    tap(&mut app, start);
+    assert!(!app.world.resource::<PauseMenu>().open);
+    app.update();
+    app.update();
+    assert!(app.world.get::<Transform>(bullet).unwrap().translation.x > bullet_position.x);
+}
+
+#[test]
+fn the_settings_table_reads_and_writes_every_setting() {
+    let mut player_config = PlayerConfig::default();
+    let mut bullet_config = BulletConfig::default();
+    for index in 0..SETTING_COUNT {
+        let label = read_setting(index, &player_config, &bullet_config, |setting| {
+            setting.label
+        });
+        assert!(label.is_some(), "{index}");
+    }
+    assert!(read_setting(SETTING_COUNT, &player_config, &bullet_config, |_| ()).is_none());
+    assert!(setting_mut(SETTING_COUNT, &mut player_config, &mut bullet_config).is_none());
+
+    let Some(SettingValue::Bool(invincible)) =
+        setting_mut(4, &mut player_config, &mut bullet_config).map(|setting| setting.value)
+    else {
+        panic!("setting 4 is invincibility");
+    };
+    *invincible = true;
+    assert!(player_config.invincible);
+}
+
+#[test]
+fn settings_and_presets_survive_a_round_trip_through_ron() {
+    let directory = std::env::temp_dir().join(format!("hacker-wars-test-{}", std::process::id()));
+    for (name, settings) in Settings::builtin_presets() {
+        let path = directory.join(format!("{name}.ron"));
+        settings.save(&path).unwrap();
+        assert_eq!(Settings::load(&path).unwrap(), settings, "{name}");
+    }
+    std::fs::remove_dir_all(directory).unwrap();
+}
+
+#[test]
+fn out_of_range_settings_fail_validation() {
+    assert_eq!(Settings::classic().validate(), Ok(()));
+    let mut settings = Settings::classic();
+    settings.player_config.shooting_delay = -0.1;
+    settings.player_config.scale = 0.0;
+    assert_eq!(
+        settings.validate(),
+        Err(
+            "shooting delay must be from 0.01 to 1, player size must be from 10 to 100".to_string()
+        )
+    );
+    let mut settings = Settings::classic();
+    settings.player_config.scale = 1e6;
+    settings.player_config.starting_health = 1_000_000_000;
+    settings.bullet_config.speed = f32::NAN;
+    assert_eq!(
+        settings.validate(),
+        Err(
+            "player size must be from 10 to 100, bullet speed must be from 50 to 1500, \
+             health must be from 1 to 1000"
+                .to_string()
+        )
+    );
+    for (name, preset) in Settings::builtin_presets() {
+        assert_eq!(preset.validate(), Ok(()), "{name}");
+    }
+}
+
+#[test]
+fn config_file_edits_apply_live_unless_invalid() {
+    let path = std::env::temp_dir().join(format!("hacker-wars-{}.ron", std::process::id()));
+    let mut settings = Settings::classic();
+    settings.bullet_config.speed = 1234.0;
+    settings.save(&path).unwrap();
+    let mut app = test_app();
+    app.add_plugins(ConfigFilePlugin { path: path.clone() });
+
+    app.update();
+    assert_eq!(app.world.resource::<BulletConfig>().speed, 1234.0);
+    assert_eq!(app.world.resource::<ConfigFile>().error, None);
+
+    let mut invalid = settings.clone();
+    invalid.player_config.shooting_delay = -1.0;
+    invalid.bullet_config.speed = 99.0;
+    invalid.save(&path).unwrap();
+    for _ in 0..60 {
+        app.update();
+    }
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(app.world.resource::<BulletConfig>().speed, 1234.0);
+    assert_eq!(
+        app.world.resource::<PlayerConfig>().shooting_delay,
+        settings.player_config.shooting_delay
+    );
+    assert_eq!(
+        app.world.resource::<ConfigFile>().error.as_deref(),
+        Some("shooting delay must be from 0.01 to 1")
+    );
+}
+
+#[test]
+fn the_command_line_sets_up_the_match() {
+    let args = |line: &str| {
+        line.split_whitespace()
+            .map(String::from)
+            .collect::<Vec<_>>()
+    };
+    let cli = Cli::parse(args(
+        "--window 800x600 --fullscreen --preset Sniper --seed 7",
+    ))
+    .unwrap();
+    assert_eq!(cli.window_size, Vec2::new(800.0, 600.0));
+    assert!(cli.fullscreen);
+    assert_eq!(cli.seed, Some(7));
+    let sniper = Settings::builtin_presets()
+        .into_iter()
+        .find(|(name, _)| *name == "Sniper")
+        .unwrap()
+        .1;
+    assert_eq!(cli.settings().unwrap(), sniper);
+
+    let cli = Cli::parse(args("--headless --bots 4 --ticks 100")).unwrap();
+    assert_eq!((cli.headless, cli.bots, cli.ticks), (true, Some(4), 100));
+    assert_eq!(cli.settings().unwrap(), Settings::classic());
+    assert_eq!(cli.personalities(), Personality::builtin());
+
+    // Balance runs can compare config files as well as presets, so a value can be swept
+    // without saving presets for it.
+    let path = std::env::temp_dir().join(format!("hacker-wars-sweep-{}.ron", std::process::id()));
+    let mut fast = Settings::classic();
+    fast.player_config.speed *= 2.0;
+    fast.save(&path).unwrap();
+    let cli = Cli::parse(args(&format!(
+        "--balance 2 --preset Sniper --config {}",
+        path.display()
+    )))
+    .unwrap();
+    let variants = cli.variants().unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(variants.len(), 2);
+    assert_eq!(
+        (variants[0].1.clone(), variants[1].1.clone()),
+        (sniper.clone(), fast)
+    );
+
+    let cli = Cli::parse(args("--rollback 7777 --peers 192.168.1.5:7777,local")).unwrap();
+    assert_eq!(cli.peers, vec![Some("192.168.1.5:7777".into()), None]);
+    assert_eq!(cli.settings().unwrap(), Settings::classic());
+
+    let cli = Cli::parse(args("--map outposts --mode deathmatch")).unwrap();
+    assert_eq!((cli.map, cli.mode), (Map::Outposts, Mode::Deathmatch));
+
+    let cli = Cli::parse(args("--headless --bots 3 --difficulty easy,hard")).unwrap();
+    let difficulties: Vec<Difficulty> = cli
+        .bots()
+        .into_iter()
+        .map(|(difficulty, _)| difficulty)
+        .collect();
+    assert_eq!(
+        difficulties,
+        [Difficulty::Easy, Difficulty::Hard, Difficulty::Easy]
+    );
+
+    let cli = Cli::parse(args("--join 192.168.1.5:7777 --spectate")).unwrap();
+    assert!(cli.spectate);
+
+    for bad in [
+        "--window 800",
+        "--seed",
+        "--bots 4 --replay a.replay",
+        "--bots 2 --difficulty insane",
+        "--headless --difficulty hard",
+        "--personality cautious",
+        "--map moon",
+        "--mode capture-the-flag",
+        "--replay a.replay --map outposts",
+        "--replay a.replay --preset Tank",
+        "--preset Classic,Tank",
+        "--preset Classic --config a.ron",
+        "--config a.ron,b.ron",
+        "--balance 10 --replay a.replay",
+        "--host 7777 --replay a.replay",
+        "--join 192.168.1.5:7777 --bots 2",
+        "--rollback 7777",
+        "--rollback 7777 --peers local",
+        "--rollback 7777 --peers 192.168.1.5:7777,192.168.1.6:7777",
+        "--rollback 7777 --peers local,192.168.1.5:7777 --bots 2",
+        "--headless --spectate",
+        "--rollback 7777 --peers local,192.168.1.5:7777 --spectate",
+        "--frobnicate",
+    ] {
+        assert!(Cli::parse(args(bad)).is_err(), "{bad}");
+    }
+}
+
+#[test]
+fn bots_lead_moving_targets() {
+    let (target, velocity, speed) = (Vec2::new(300.0, 0.0), Vec2::new(0.0, 100.0), 600.0);
+    let aim = lead_target(Vec2::ZERO, target, velocity, speed);
+    let time = (aim.y - target.y) / velocity.y;
+    assert!(time > 0.0);
+    assert!((aim.length() - speed * time).abs() < 0.01);
+    assert_eq!(lead_target(Vec2::ZERO, target, Vec2::ZERO, speed), target);
+}
+
+#[test]
+fn bots_join_aim_at_enemies_and_leave() {
+    let mut app = test_app();
+    app.add_plugins(BotPlugin).insert_resource(Bots::seeded(0));
+    let enemy = spawn_player(&mut app, 0, Vec2::new(300.0, 0.0));
+    let id = app
+        .world
+        .resource_mut::<Bots>()
+        .add(Difficulty::Hard, "balanced")
+        .unwrap();
+    let tick = |app: &mut App| {
+        *app.world.resource_mut::<TickInputs>() = TickInputs::default();
+        app.update();
+    };
+    for _ in 0..10 {
+        tick(&mut app);
+    }
+
+    let (bot, position) = app
+        .world
+        .query::<(Entity, &ID, &Transform)>()
+        .iter(&app.world)
+        .find(|(_, player_id, _)| player_id.0 == id)
+        .map(|(entity, _, transform)| (entity, transform.translation.truncate()))
+        .unwrap();
+    let aim = app.world.resource::<TickInputs>().player(id).unwrap().aim;
+    let enemy_position = app
+        .world
+        .get::<Transform>(enemy)
+        .unwrap()
+        .translation
+        .truncate();
+    assert!(aim.angle_between(enemy_position - position).abs() < 0.05);
+
+    app.world.resource_mut::<Bots>().remove(id);
+    for _ in 0..80 {
+        tick(&mut app);
+    }
+    assert!(app.world.get_entity(bot).is_none());
+    assert!(app.world.resource::<Bots>().bots.is_empty());
+}
+
+/// Adds a bot to a match on the outposts map, and returns it once it has joined.
+fn outposts_bot(app: &mut App, personality: &str) -> (usize, Entity) {
+    app.insert_resource(Map::Outposts)
+        .add_plugins(BotPlugin)
+        .insert_resource(Bots::seeded(0));
+    let id = app
+        .world
+        .resource_mut::<Bots>()
+        .add(Difficulty::Hard, personality)
+        .unwrap();
+    for _ in 0..5 {
+        *app.world.resource_mut::<TickInputs>() = TickInputs::default();
+        app.update();
+    }
+    let bot = app
+        .world
+        .query::<(Entity, &ID)>()
+        .iter(&app.world)
+        .find(|(_, player_id)| player_id.0 == id)
+        .unwrap()
+        .0;
+    (id, bot)
+}
+
+/// Runs the match for a second, returning the actions the bot chose along the way.
+fn bot_actions(app: &mut App) -> Vec<Action> {
+    let mut actions = vec![];
+    for _ in 0..64 {
+        *app.world.resource_mut::<TickInputs>() = TickInputs::default();
+        app.update();
+        actions.extend(app.world.resource::<Bots>().bots[0].action);
+    }
+    actions
+}
+
+#[test]
+fn hurt_bots_take_cover_behind_walls() {
+    let mut app = test_app();
+    let wall = Map::Outposts.walls(Vec2::new(750.0, 500.0))[0];
+    let enemy = spawn_player(&mut app, 0, Vec2::new(-600.0, wall.center().y));
+    let (_, bot) = outposts_bot(&mut app, "cautious");
+    let start = Vec2::new(wall.max.x + 80.0, wall.center().y);
+    app.world.get_mut::<Transform>(bot).unwrap().translation = start.extend(64.0);
+    app.world.get_mut::<Health>(bot).unwrap().current_health = 1;
+
+    let actions = bot_actions(&mut app);
+    assert!(actions.contains(&Action::TakeCover), "{actions:?}");
+    let position = app.world.get::<Transform>(bot).unwrap().translation;
+    let enemy_position = app.world.get::<Transform>(enemy).unwrap().translation;
+    // The wall stands between them.
+    assert!(
+        enemy_position.x < wall.min.x && position.x > wall.max.x,
+        "at {position}"
+    );
+    assert!(
+        (position.y - wall.center().y).abs() < wall.height() / 2.0,
+        "at {position}"
+    );
+    assert!(position.x < start.x, "at {position}");
+}
+
+#[test]
+fn personalities_choose_actions_by_utility() {
+    let personalities = Personality::builtin();
+    let balanced = &personalities
+        .iter()
+        .find(|(name, _)| name == "balanced")
+        .unwrap()
+        .1;
+    let fighting = Situation {
+        health: 1.0,
+        target_health: Some(1.0),
+        target_distance: Some(400.0),
+        danger: 0.0,
+        cover_distance: None,
+    };
+    assert_eq!(balanced.choose(&fighting), Some(Action::Strafe));
+    let far = Situation {
+        target_distance: Some(1000.0),
+        ..fighting.clone()
+    };
+    assert_eq!(balanced.choose(&far), Some(Action::Chase));
+    let hurt = Situation {
+        health: 0.1,
+        ..fighting.clone()
+    };
+    assert_eq!(balanced.choose(&hurt), Some(Action::Retreat));
+    let hurt_by_a_wall = Situation {
+        cover_distance: Some(100.0),
+        ..hurt.clone()
+    };
+    assert_eq!(balanced.choose(&hurt_by_a_wall), Some(Action::TakeCover));
+    let shot_at = Situation {
+        danger: 0.8,
+        ..hurt.clone()
+    };
+    assert_eq!(balanced.choose(&shot_at), Some(Action::Dodge));
+    let alone = Situation {
+        target_health: None,
+        target_distance: None,
+        ..fighting
+    };
+    assert_eq!(balanced.choose(&alone), Some(Action::Wander));
+}
+
+#[test]
+fn balance_runs_sum_up_repeatable_bot_matches() {
+    let cli = Cli::parse(
+        "--balance 3 --preset Classic,Sniper --bots 2 --ticks 600 --seed 5"
+            .split_whitespace()
+            .map(String::from),
+    )
+    .unwrap();
+    let mut bots = Bots::new(None, cli.personalities());
+    for (difficulty, personality) in cli.bots() {
+        bots.add(difficulty, &personality).unwrap();
+    }
+    let balance = Balance {
+        variants: cli.variants().unwrap(),
+        bots,
+        map: cli.map,
+        mode: cli.mode,
+        matches: cli.balance.unwrap(),
+        ticks: cli.ticks,
+        seed: cli.seed.unwrap(),
+    };
+    let rows = balance.run(2, |_, _| {});
+    assert_eq!(rows.len(), 4);
+    assert_eq!(
+        (rows[0].variant.as_str(), rows[3].variant.as_str()),
+        ("Classic", "Sniper")
+    );
+    for row in &rows {
+        assert_eq!(row.matches, 3);
+        assert!(row.wins + row.draws <= 3);
+        assert!(row.hits <= row.shots);
+    }
+    let kills: u32 = rows.iter().map(|row| row.kills).sum();
+    let deaths: u32 = rows.iter().map(|row| row.deaths).sum();
+    assert_eq!(kills, deaths);
+
+    // Matches are seeded by their number, so it doesn't matter which thread plays them.
+    assert_eq!(balance.run(1, |_, _| {}), rows);
+
+    let mut csv = vec![];
+    write_csv(&rows, &mut csv).unwrap();
+    let csv = String::from_utf8(csv).unwrap();
+    let lines: Vec<_> = csv.lines().collect();
+    assert_eq!(lines.len(), 5);
+    assert_eq!(lines[0], CSV_HEADER);
+    assert!(lines[1].starts_with("Classic,0,normal,balanced,3,"));
+}
+
+#[test]
+fn lan_clients_play_as_normal_players_on_the_host() {
+    let mut host = test_app();
+    host.add_plugins(NetHostPlugin)
+        .insert_resource(NetHost::bind("127.0.0.1:0").unwrap());
+    let address = host.world.resource::<NetHost>().local_addr().unwrap();
+    let mut client = test_app();
+    client
+        .add_plugins(NetClientPlugin)
+        .insert_resource(NetClient::connect(address).unwrap());
+    // Loopback delivers as soon as a datagram is sent, so a tick each way is a round trip.
+    let tick = |host: &mut App, client: &mut App, input: PlayerInput| {
+        let mut local = client.world.resource_mut::<LocalControllers>();
+        local.controllers = vec![Connection {
+            id: 0,
+            name: "Pad".into(),
+        }];
+        local.players = vec![input];
+        client.update();
+        *host.world.resource_mut::<TickInputs>() = TickInputs::default();
+        host.update();
+    };
+    let players = |app: &mut App| {
+        app.world
+            .query::<(&ID, &Transform, &Controller, Option<&Alive>)>()
+            .iter(&app.world)
+            .map(|(id, transform, controller, alive)| {
+                (
+                    id.0,
+                    transform.translation.truncate(),
+                    controller.name.clone(),
+                    alive.is_some(),
+                )
+            })
+            .collect::<Vec<_>>()
+    };
+
+    let start = PlayerInput {
+        id: 0,
+        held: vec![GamepadButtonType::Start],
+        ..default()
+    };
+    tick(&mut host, &mut client, start.clone());
+    tick(&mut host, &mut client, start);
+    assert_eq!(host.world.resource::<NetHost>().clients().len(), 1);
+    let on_host = players(&mut host);
+    assert_eq!(on_host.len(), 1);
+    let (id, position, controller, alive) = on_host[0].clone();
+    assert_eq!(id, REMOTE_ID_OFFSET);
+    assert_eq!(controller, "Pad (127.0.0.1)");
+    assert!(alive);
+
+    for _ in 0..10 {
+        tick(
+            &mut host,
+            &mut client,
+            PlayerInput {
+                id: 0,
+                movement: Vec2::X,
+                ..default()
+            },
+        );
+    }
+    let moved = players(&mut host)[0].1;
+    assert!(moved.x > position.x);
+    // The client shows the host's state as of the tick before.
+    client.update();
+    assert_eq!(players(&mut client), players(&mut host));
+    // With a shooter for the HUD's cooldown bar, which never fires on the client.
+    let shooters = client.world.query::<&Shooter>().iter(&client.world).count();
+    assert_eq!(shooters, 1);
+
+    // Mode takes the player out, on the host and then the client.
+    let mode = PlayerInput {
+        id: 0,
+        held: vec![GamepadButtonType::Mode],
+        ..default()
+    };
+    tick(&mut host, &mut client, mode.clone());
+    tick(&mut host, &mut client, mode);
+    client.update();
+    assert!(!players(&mut host)[0].3);
+    assert!(!players(&mut client)[0].3);
+    let markers = client
+        .world
+        .query::<&DeathMarker>()
+        .iter(&client.world)
+        .count();
+    assert_eq!(markers, 1);
+
+    // Unplugging the controller leaves the match after the reconnect grace.
+    client
+        .world
+        .resource_mut::<LocalControllers>()
+        .controllers
+        .clear();
+    for _ in 0..80 {
+        client.update();
+        *host.world.resource_mut::<TickInputs>() = TickInputs::default();
+        host.update();
+    }
+    client.update();
+    assert!(players(&mut host).is_empty());
+    assert!(players(&mut client).is_empty());
+}
+
+#[test]
+fn a_full_host_turns_clients_away_until_one_leaves() {
+    let mut host = test_app();
+    host.add_plugins(NetHostPlugin)
+        .insert_resource(NetHost::bind("127.0.0.1:0").unwrap());
+    let address = host.world.resource::<NetHost>().local_addr().unwrap();
+    let mut clients: Vec<App> = (0..=MAX_CLIENTS)
+        .map(|_| {
+            let mut client = test_app();
+            client
+                .add_plugins(NetClientPlugin)
+                .insert_resource(NetClient::connect(address).unwrap());
+            client
+        })
+        .collect();
+    let last = clients.last_mut().unwrap();
+    last.add_systems(Update, update_join_slots);
+    last.world
+        .spawn((TextBundle::from_section("", default()), JoinSlotText(0)));
+    let round = |host: &mut App, clients: &mut [App]| {
+        for client in clients.iter_mut() {
+            client.update();
+        }
+        *host.world.resource_mut::<TickInputs>() = TickInputs::default();
+        host.update();
+    };
+    let host_full = |clients: &[App]| {
+        clients
+            .iter()
+            .map(|client| client.world.resource::<NetClient>().is_host_full())
+            .collect::<Vec<_>>()
+    };
+
+    round(&mut host, &mut clients);
+    round(&mut host, &mut clients);
+    assert_eq!(
+        host.world.resource::<NetHost>().clients().len(),
+        MAX_CLIENTS
+    );
+    let mut expected = vec![false; MAX_CLIENTS + 1];
+    expected[MAX_CLIENTS] = true;
+    assert_eq!(host_full(&clients), expected);
+    let last = clients.last_mut().unwrap();
+    let text = last.world.query::<&Text>().single(&last.world).sections[0]
+        .value
+        .clone();
+    assert!(text.contains("Host is full"), "{text}");
+
+    // Once a client leaves, the one turned away gets its place.
+    clients[0].world.send_event(AppExit);
+    clients[0].update();
+    clients.remove(0);
+    round(&mut host, &mut clients);
+    round(&mut host, &mut clients);
+    assert_eq!(
+        host.world.resource::<NetHost>().clients().len(),
+        MAX_CLIENTS
+    );
+    assert_eq!(host_full(&clients), vec![false; MAX_CLIENTS]);
+}
+
+#[test]
+fn clients_joining_late_dont_see_earlier_deaths() {
+    let mut host = test_app();
+    host.add_plugins(NetHostPlugin)
+        .insert_resource(NetHost::bind("127.0.0.1:0").unwrap());
+    let address = host.world.resource::<NetHost>().local_addr().unwrap();
+    connect(&mut host, 0, "Pad");
+    // Mode takes the player out, and the death is still being resent when the spectator comes.
+    press(&mut host, 0, GamepadButtonType::Mode);
+    let mut spectator = test_app();
+    spectator
+        .add_plugins(NetClientPlugin)
+        .insert_resource(NetClient::spectate(address).unwrap());
+    for _ in 0..3 {
+        spectator.update();
+        host.update();
+    }
+    spectator.update();
+
+    let deaths = |app: &App| {
+        let stats = app.world.resource::<MatchStats>();
+        stats
+            .players
+            .iter()
+            .map(|player| player.deaths)
+            .sum::<u32>()
+    };
+    assert_eq!(deaths(&host), 1);
+    assert_eq!(deaths(&spectator), 0);
+}
+
+#[test]
+fn snapshots_leave_out_the_bullets_that_dont_fit_in_a_datagram() {
+    let mut host = test_app();
+    host.add_plugins(NetHostPlugin)
+        .insert_resource(NetHost::bind("127.0.0.1:0").unwrap());
+    host.world.resource_mut::<BulletConfig>().collide = false;
+    let address = host.world.resource::<NetHost>().local_addr().unwrap();
+    let mut spectator = test_app();
+    spectator
+        .add_plugins(NetClientPlugin)
+        .insert_resource(NetClient::spectate(address).unwrap());
+    // Players with long controller names leave less room for bullets.
+    for id in 0..MAX_PLAYERS {
+        connect(&mut host, id, &"Pad".repeat(100));
+    }
+    for i in 0..10_000 {
+        spawn_bullet(&mut host, usize::MAX, Vec2::new(-700.0, i as f32 / 20.0));
+    }
+    spectator.update();
+    host.update();
+    spectator.update();
+
+    // A snapshot too big for a datagram wouldn't arrive at all.
+    let world = &mut spectator.world;
+    let players = world
+        .query_filtered::<(), With<Player>>()
+        .iter(world)
+        .count();
+    assert_eq!(players, MAX_PLAYERS);
+    let bullets = world
+        .query_filtered::<(), With<Bullet>>()
+        .iter(world)
+        .count();
+    assert!((1000..10_000).contains(&bullets), "{bullets}");
+}
+
+#[test]
+fn spectators_watch_without_joining() {
+    let mut host = test_app();
+    host.add_plugins(NetHostPlugin)
+        .insert_resource(NetHost::bind("127.0.0.1:0").unwrap());
+    let address = host.world.resource::<NetHost>().local_addr().unwrap();
+    let mut spectator = test_app();
+    spectator
+        .add_plugins(NetClientPlugin)
+        .insert_resource(NetClient::spectate(address).unwrap());
+    // The spectator's controller pressing Start doesn't join.
+    let mut local = spectator.world.resource_mut::<LocalControllers>();
+    local.controllers = vec![Connection {
+        id: 0,
+        name: "Pad".into(),
+    }];
+    local.players = vec![PlayerInput {
+        id: 0,
+        held: vec![GamepadButtonType::Start],
+        ..default()
+    }];
+    connect(&mut host, 0, "Host pad");
+    connect(&mut host, 3, "Other pad");
+    for _ in 0..3 {
+        spectator.update();
+        *host.world.resource_mut::<TickInputs>() = TickInputs::default();
+        host.update();
+    }
+    spectator.update();
+
+    let net_host = host.world.resource::<NetHost>();
+    assert!(net_host.clients().is_empty());
+    assert_eq!(net_host.spectators().len(), 1);
+    let ids = |app: &mut App| {
+        let mut ids: Vec<usize> = app
+            .world
+            .query_filtered::<&ID, With<Player>>()
+            .iter(&app.world)
+            .map(|id| id.0)
+            .collect();
+        ids.sort();
+        ids
+    };
+    assert_eq!(ids(&mut host), vec![0, 3]);
+    let watched = ids(&mut spectator);
+    assert_eq!(watched, vec![0, 3]);
+
+    // Following goes through the players by ID and wraps around.
+    let mut camera = Spectator::default();
+    camera.follow_next(&watched);
+    assert_eq!(camera.following, Some(0));
+    camera.follow_next(&watched);
+    assert_eq!(camera.following, Some(3));
+    camera.follow_next(&watched);
+    assert_eq!(camera.following, Some(0));
+    camera.follow_previous(&watched);
+    assert_eq!(camera.following, Some(3));
+}
+
+#[test]
+fn rollback_peers_converge_despite_latency() {
+    let sockets = [(); 2].map(|_| UdpSocket::bind("127.0.0.1:0").unwrap());
+    let addresses = sockets
+        .each_ref()
+        .map(|socket| Some(socket.local_addr().unwrap()));
+    let mut index = 0;
+    let mut peers = sockets.map(|socket| {
+        let mut app = test_app();
+        app.world.resource_mut::<PlayerConfig>().shooting_delay = 0.25;
+        let mut listed = addresses.to_vec();
+        listed[index] = None;
+        let mut session = RollbackSession::new(socket, listed).unwrap();
+        // Different each way, so that the peers roll back at different times. On top of the
+        // frame it takes the peers to take turns.
+        session.latency = [5, 2][index];
+        index += 1;
+        app.add_plugins(RollbackPlugin { seed: 0 })
+            .insert_resource(session);
+        app
+    });
+    let players = |app: &mut App| {
+        let mut players = app
+            .world
+            .query_filtered::<(&ID, &Transform), With<Player>>()
+            .iter(&app.world)
+            .map(|(id, transform)| (id.0, transform.translation.truncate()))
+            .collect::<Vec<_>>();
+        players.sort_by_key(|(id, _)| *id);
+        players
+    };
+
+    for frame in 0..300 {
+        for (index, app) in peers.iter_mut().enumerate() {
+            // Circling, with the first aiming at the second as it sees them, so that bullets
+            // hit, and the second spinning, so that theirs mostly don't meet the first's.
+            let seen = players(app);
+            let position = |id| seen.iter().find(|(other, _)| *other == id).map(|p| p.1);
+            let aim = match (
+                position(remote_id(index, 0)),
+                position(remote_id(1 - index, 0)),
+            ) {
+                (Some(from), Some(to)) if index == 0 => (to - from).normalize_or_zero(),
+                _ => Vec2::from_angle(frame as f32 / 5.0),
+            };
+            let held = match frame {
+                2..=4 => vec![GamepadButtonType::Start],
+                // The first takes themselves out and comes back.
+                60 | 70 if index == 0 => vec![GamepadButtonType::Mode],
+                _ => vec![],
+            };
+            let input = match frame {
+                0..=239 => PlayerInput {
+                    id: 0,
+                    movement: Vec2::from_angle(frame as f32 / 10.0 + index as f32 * 3.0) * 0.2,
+                    aim,
+                    held,
+                    ..default()
+                },
+                // Then standing still, so that everything is guessed right in the end.
+                _ => PlayerInput { id: 0, ..default() },
+            };
+            let mut local = app.world.resource_mut::<LocalControllers>();
+            local.controllers = vec![Connection {
+                id: 0,
+                name: "Pad".into(),
+            }];
+            local.players = vec![input];
+            app.update();
+        }
+    }
+
+    let [first, second] = &mut peers;
+    let sessions = [&first, &second].map(|app| app.world.resource::<RollbackSession>());
+    for session in sessions {
+        assert_eq!(session.desync, None);
+        assert!(session.rollbacks > 0);
+    }
+    let (ours, theirs) = (sessions[0].checksums(), sessions[1].checksums());
+    let common: Vec<u32> = ours
+        .keys()
+        .filter(|tick| theirs.contains_key(tick))
+        .copied()
+        .collect();
+    assert!(common.len() > 250, "{} ticks in common", common.len());
+    for tick in common {
+        assert_eq!(ours[&tick], theirs[&tick], "tick {tick}");
+    }
+    let stats = first.world.resource::<MatchStats>();
+    assert!(stats
+        .players
+        .iter()
+        .all(|player| player.shots > 0 && player.deaths > 0));
+    assert!(stats.players.iter().any(|player| player.kills > 0));
+    assert_eq!(players(first).len(), 2);
+    assert_eq!(players(first), players(second));
+}